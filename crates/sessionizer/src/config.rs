@@ -1,28 +1,95 @@
+use std::collections::BTreeMap;
+
 use clap::{Parser, Subcommand};
 use color_eyre::eyre::{eyre, Result, WrapErr};
 use serde::{Deserialize, Serialize};
 
-#[derive(Debug, Serialize, Deserialize)]
+use crate::hooks::Hooks;
+use crate::naming::Naming;
+use crate::picker::Picker;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Config {
     pub directories: Vec<crate::directories::Directory>,
+    /// Legacy session history, copied to the state file the first time it's loaded and ignored
+    /// afterwards.
+    ///
+    /// It's kept when the configuration is rewritten, so no command loses it.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
     pub sessions: Vec<String>,
+    #[serde(default)]
     pub env: Vec<String>,
+    #[serde(default, skip_serializing_if = "Hooks::is_empty")]
+    pub hooks: Hooks,
+    /// Globs of folders never scanned inside any directory, e.g. `node_modules`.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub exclude: Vec<String>,
+    /// Seconds a directory scan is reused for, `0` disabling the cache.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub cache_ttl: Option<u64>,
+    #[serde(default, skip_serializing_if = "crate::worktree::Options::is_empty")]
+    pub worktree: crate::worktree::Options,
+    #[serde(default)]
+    pub picker: Picker,
+    /// Command previewing the highlighted item, e.g. `ls {}`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub preview: Option<String>,
+    /// How session names are derived from directories.
+    #[serde(default, skip_serializing_if = "Naming::is_default")]
+    pub naming: Naming,
+    /// Explicit session names of some directories, keyed by path.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub names: BTreeMap<String, String>,
+    #[serde(default, skip_serializing_if = "crate::sync::Options::is_empty")]
+    pub sync: crate::sync::Options,
+    #[serde(default, skip_serializing_if = "crate::state::Policy::is_empty")]
+    pub history: crate::state::Policy,
     #[serde(skip)]
     path: String,
 }
 
 impl Config {
     pub fn new(path: &str) -> Self {
-        Self { directories: vec![], sessions: vec![], env: vec![], path: path.to_string() }
+        Self {
+            directories: vec![],
+            sessions: vec![],
+            env: vec![],
+            hooks: Hooks::default(),
+            exclude: vec![],
+            cache_ttl: None,
+            worktree: Default::default(),
+            picker: Picker::default(),
+            preview: None,
+            naming: Naming::default(),
+            names: BTreeMap::new(),
+            sync: Default::default(),
+            history: Default::default(),
+            path: path.to_string(),
+        }
+    }
+
+    /// Path of the configuration file.
+    pub fn path(&self) -> &str {
+        &self.path
     }
 
     pub fn save(&self) -> Result<()> {
         let text = serde_yaml::to_string(&self).wrap_err("fail to serialize config")?;
 
-        std::fs::write(&self.path, text).wrap_err("fail to save config")?;
+        crate::files::write_atomic(&self.path, text.as_bytes()).wrap_err("fail to save config")?;
         Ok(())
     }
 
+    /// Applies `f` to the latest configuration on disk and saves the result while holding the
+    /// configuration lock.
+    pub fn update(&mut self, f: impl FnOnce(&mut Self)) -> Result<()> {
+        let _lock = crate::files::Lock::acquire(&self.path)?;
+
+        *self = Self::load(&self.path)?;
+        f(self);
+        self.save()
+    }
+
     pub fn load(path: &str) -> Result<Self> {
         if !std::path::Path::new(path).exists() {
             return Err(eyre!("Configuration file does not exist."));
@@ -38,6 +105,79 @@ impl Config {
     pub fn home() -> Result<String> {
         Ok(format!("{}/.sessionizer.yaml", std::env::var("HOME")?))
     }
+
+    /// Returns the expanded `KEY=value` environment for a session rooted at `session`.
+    ///
+    /// The global `env` list is applied first, followed by the `env` list of every tracked
+    /// directory that contains the session, from the outermost to the innermost one, and finally
+    /// the `overrides`, e.g. the `env` of the project-local configuration. Entries that can't be
+    /// expanded are reported and skipped.
+    pub fn env(&self, session: &str, overrides: &[String]) -> Vec<String> {
+        let mut directories: Vec<_> =
+            self.directories.iter().filter(|d| d.contains(session)).collect();
+        directories.sort_by_key(|d| d.path.len());
+
+        let mut env: Vec<(String, String)> = Vec::new();
+        for entry in self
+            .env
+            .iter()
+            .chain(directories.iter().flat_map(|d| d.env.iter()))
+            .chain(overrides.iter())
+        {
+            let (key, value) = match expand_env(entry) {
+                Ok(pair) => pair,
+                Err(err) => {
+                    eprintln!("Skipping env entry: {:#}", err);
+                    continue;
+                }
+            };
+            match env.iter_mut().find(|(k, _)| k == &key) {
+                Some(existing) => existing.1 = value,
+                None => env.push((key, value)),
+            }
+        }
+
+        env.into_iter().map(|(key, value)| format!("{}={}", key, value)).collect()
+    }
+
+    /// Returns the hooks of a session rooted at `session`.
+    ///
+    /// Global hooks run first, followed by the ones of every tracked directory that contains the
+    /// session, from the outermost to the innermost one, and finally the `overrides`.
+    pub fn hooks(&self, session: &str, overrides: &Hooks) -> Hooks {
+        let mut directories: Vec<_> =
+            self.directories.iter().filter(|d| d.contains(session)).collect();
+        directories.sort_by_key(|d| d.path.len());
+
+        let mut hooks = self.hooks.clone();
+        for directory in directories {
+            hooks.extend(&directory.hooks);
+        }
+        hooks.extend(overrides);
+
+        hooks
+    }
+
+    /// Returns the layout of the innermost tracked directory containing `session`.
+    pub fn layout(&self, session: &str) -> Option<&crate::layout::Layout> {
+        self.directories
+            .iter()
+            .filter(|d| d.layout.is_some() && d.contains(session))
+            .max_by_key(|d| d.path.len())
+            .and_then(|d| d.layout.as_ref())
+    }
+}
+
+/// Splits a `KEY=value` entry and expands `~` and `$VAR` references in its value.
+fn expand_env(entry: &str) -> Result<(String, String)> {
+    let (key, value) = entry
+        .split_once('=')
+        .filter(|(key, _)| !key.is_empty())
+        .ok_or_else(|| eyre!("invalid env entry {:?}, expected KEY=value", entry))?;
+    let value = shellexpand::full(value)
+        .wrap_err_with(|| format!("fail to expand env entry {:?}", entry))?;
+
+    Ok((key.to_string(), value.into_owned()))
 }
 
 #[derive(Debug, Subcommand)]
@@ -55,6 +195,20 @@ pub enum Commands {
     /// Prints the sessionizer configuration to stdout
     #[clap(name = "print")]
     Print,
+    /// Trust the project configuration file of a directory
+    #[clap(name = "trust")]
+    Trust {
+        /// Directory containing a `.sessionizer.yaml` or `.sessionizer.toml` file.
+        #[clap(default_value = ".")]
+        dir: String,
+    },
+    /// Stop trusting the project configuration file of a directory
+    #[clap(name = "untrust")]
+    Untrust {
+        /// Directory containing a `.sessionizer.yaml` or `.sessionizer.toml` file.
+        #[clap(default_value = ".")]
+        dir: String,
+    },
 }
 
 #[derive(Debug, Parser)]
@@ -70,9 +224,29 @@ pub async fn run(path: &str, cli: Cli) -> Result<()> {
         Commands::Init { force } => init(path, force).await,
         Commands::Edit => edit().await,
         Commands::Print => print(path).await,
+        Commands::Trust { dir } => trust(path, &dir).await,
+        Commands::Untrust { dir } => untrust(path, &dir).await,
     }
 }
 
+pub async fn trust(path: &str, dir: &str) -> Result<()> {
+    let mut trust = crate::project::Trust::load(path)?;
+    let file = trust.update(|trust| trust.allow(dir))?;
+
+    println!("Trusted {}.", file.display());
+
+    Ok(())
+}
+
+pub async fn untrust(path: &str, dir: &str) -> Result<()> {
+    let mut trust = crate::project::Trust::load(path)?;
+    let file = trust.update(|trust| trust.revoke(dir))?;
+
+    println!("Stopped trusting {}.", file.display());
+
+    Ok(())
+}
+
 pub async fn print(path: &str) -> Result<()> {
     println!("{:#?}", Config::load(path));
 
@@ -84,7 +258,11 @@ pub async fn init(path: &str, force: bool) -> Result<()> {
         return Err(eyre!("Configuration file already exists. Use --force to override."));
     }
 
-    let config = Config::new(path);
+    let mut config = Config::new(path);
+    // The legacy history may not have been moved to the state file yet.
+    if let Ok(previous) = Config::load(path) {
+        config.sessions = previous.sessions;
+    }
 
     config.save()?;
 
@@ -116,3 +294,100 @@ pub async fn edit() -> Result<()> {
         Err(err) => Err(eyre!("fail to wait for editor: {}", err)),
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::directories::Directory;
+
+    fn directory(path: &str, env: &[&str]) -> Directory {
+        Directory {
+            id: path.to_string(),
+            path: path.to_string(),
+            mindepth: 1,
+            maxdepth: 1,
+            env: env.iter().map(|e| e.to_string()).collect(),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn env_expands_variables_and_home() {
+        let home = std::env::var("HOME").unwrap();
+        let mut config = Config::new("sessionizer.yaml");
+        config.env = vec!["KUBECONFIG=~/.kube/config".into(), "ROOT=$HOME/src".into()];
+
+        assert_eq!(
+            config.env("/tmp", &[]),
+            vec![format!("KUBECONFIG={}/.kube/config", home), format!("ROOT={}/src", home)]
+        );
+    }
+
+    #[test]
+    fn env_is_overridden_by_the_innermost_directory() {
+        let mut config = Config::new("sessionizer.yaml");
+        config.env = vec!["AWS_PROFILE=default".into(), "EDITOR=vim".into()];
+        config.directories = vec![
+            directory("/src/work/api", &["AWS_PROFILE=api"]),
+            directory("/src/work", &["AWS_PROFILE=work", "TEAM=core"]),
+            directory("/src/personal", &["AWS_PROFILE=personal"]),
+        ];
+
+        assert_eq!(
+            config.env("/src/work/api/server", &[]),
+            vec!["AWS_PROFILE=api", "EDITOR=vim", "TEAM=core"]
+        );
+        assert_eq!(
+            config.env("/src/work/api/server", &["TEAM=api".into()]),
+            vec!["AWS_PROFILE=api", "EDITOR=vim", "TEAM=api"]
+        );
+        assert_eq!(config.env("/src/other", &[]), vec!["AWS_PROFILE=default", "EDITOR=vim"]);
+    }
+
+    #[test]
+    fn layout_comes_from_the_innermost_directory() {
+        let layout = |name: &str| crate::layout::Layout {
+            windows: vec![crate::layout::Window { name: Some(name.into()), ..Default::default() }],
+        };
+        let mut config = Config::new("sessionizer.yaml");
+        config.directories = vec![
+            Directory { layout: Some(layout("work")), ..directory("/src/work", &[]) },
+            directory("/src/work/api", &[]),
+            Directory { layout: Some(layout("web")), ..directory("/src/work/web", &[]) },
+        ];
+
+        assert_eq!(config.layout("/src/work/api/server"), Some(&layout("work")));
+        assert_eq!(config.layout("/src/work/web"), Some(&layout("web")));
+        assert_eq!(config.layout("/src/personal"), None);
+    }
+
+    #[test]
+    fn hooks_are_chained_from_the_outermost_directory() {
+        let hooks = |command: &str| Hooks { on_enter: vec![command.into()], ..Default::default() };
+        let mut config = Config::new("sessionizer.yaml");
+        config.hooks = hooks("global");
+        config.directories = vec![
+            Directory { hooks: hooks("api"), ..directory("/src/work/api", &[]) },
+            Directory { hooks: hooks("work"), ..directory("/src/work", &[]) },
+        ];
+
+        assert_eq!(
+            config.hooks("/src/work/api", &hooks("project")).on_enter,
+            vec!["global", "work", "api", "project"]
+        );
+        assert_eq!(config.hooks("/src/other", &Hooks::default()).on_enter, vec!["global"]);
+    }
+
+    #[test]
+    fn env_skips_invalid_entries() {
+        let mut config = Config::new("sessionizer.yaml");
+        config.env = vec![
+            "=value".into(),
+            "NO_VALUE".into(),
+            "MISSING=$SESSIONIZER_UNDEFINED_VARIABLE".into(),
+            "EDITOR=vim".into(),
+        ];
+
+        assert_eq!(config.env("/tmp", &[]), vec!["EDITOR=vim"]);
+    }
+}
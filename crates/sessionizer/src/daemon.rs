@@ -0,0 +1,441 @@
+//! Background process keeping the directory candidates up to date.
+//!
+//! The daemon watches the folders a scan reads and rescans the subtree of a folder as soon as it
+//! gets created, removed or renamed inside them. Clients ask for the candidates over a Unix socket and
+//! fall back to [`crate::directories::evaluate`] when the daemon isn't running.
+
+use std::collections::BTreeSet;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use color_eyre::eyre::{bail, eyre, Result, WrapErr};
+use notify::event::{CreateKind, ModifyKind, RemoveKind};
+use notify::{EventKind, RecursiveMode, Watcher};
+use tokio::io::{AsyncBufReadExt, AsyncReadExt, AsyncWriteExt, BufReader};
+use tokio::net::{UnixListener, UnixStream};
+use tokio::sync::mpsc;
+
+use crate::config::Config;
+use crate::directories::{Directory, Scan};
+
+/// Time clients wait for an answer before scanning the directories themselves.
+const TIMEOUT: Duration = Duration::from_millis(500);
+
+/// Time the daemon waits for a burst of filesystem events to settle before rescanning.
+const DEBOUNCE: Duration = Duration::from_millis(200);
+
+/// Candidate directories of every configured root.
+#[derive(Debug, Default)]
+struct Index {
+    /// Canonical path of the configuration the index was built from.
+    config: String,
+    roots: Vec<Scan>,
+}
+
+impl Index {
+    fn directories(&self) -> Vec<String> {
+        let mut directories: Vec<String> =
+            self.roots.iter().flat_map(|scan| scan.directories.iter()).cloned().collect();
+        directories.sort();
+        directories.dedup();
+        directories
+    }
+}
+
+/// Path of the daemon socket, inside `$XDG_RUNTIME_DIR` when it's set.
+pub fn socket() -> String {
+    match std::env::var("XDG_RUNTIME_DIR") {
+        Ok(dir) if !dir.is_empty() => format!("{}/sessionizer.sock", dir),
+        _ => {
+            let user = std::env::var("USER").unwrap_or_default();
+            let file = format!("sessionizer-{}.sock", user);
+            std::env::temp_dir().join(file).to_string_lossy().to_string()
+        }
+    }
+}
+
+fn canonical(path: &str) -> String {
+    std::fs::canonicalize(path)
+        .map(|p| p.to_string_lossy().to_string())
+        .unwrap_or_else(|_| path.to_string())
+}
+
+/// Asks the daemon listening on `socket` for the candidate directories of `config`.
+async fn query(socket: &str, config: &Config) -> Result<Vec<String>> {
+    let mut stream = UnixStream::connect(socket).await.wrap_err("fail to connect")?;
+    stream.write_all(format!("directories {}\n", canonical(config.path())).as_bytes()).await?;
+
+    let mut response = String::new();
+    stream.read_to_string(&mut response).await?;
+
+    let mut lines = response.lines();
+    match lines.next() {
+        Some("ok") => Ok(lines.map(String::from).collect()),
+        Some(error) => bail!("daemon error: {}", error),
+        None => bail!("empty daemon response"),
+    }
+}
+
+/// Returns the candidate directories known by the daemon, if it's running and up to date.
+pub async fn directories(config: &Config) -> Option<Vec<String>> {
+    match tokio::time::timeout(TIMEOUT, query(&socket(), config)).await {
+        Ok(Ok(directories)) => Some(directories),
+        Ok(Err(err)) => {
+            log::debug!("Not using the daemon: {:?}", err);
+            None
+        }
+        Err(_) => {
+            log::debug!("Not using the daemon: timed out");
+            None
+        }
+    }
+}
+
+async fn handle(stream: UnixStream, index: &Mutex<Index>) -> Result<()> {
+    let (reader, mut writer) = stream.into_split();
+    let mut request = String::new();
+    BufReader::new(reader).read_line(&mut request).await?;
+
+    let response = match request.trim_end().split_once(' ') {
+        Some(("directories", config)) => {
+            let index = index.lock().unwrap();
+            if index.config == config {
+                format!("ok\n{}", index.directories().join("\n"))
+            } else {
+                "the daemon runs with another configuration".to_string()
+            }
+        }
+        _ => format!("unknown request: {}", request.trim_end()),
+    };
+
+    writer.write_all(response.as_bytes()).await?;
+    writer.shutdown().await?;
+
+    Ok(())
+}
+
+async fn serve(listener: UnixListener, index: Arc<Mutex<Index>>) {
+    loop {
+        let stream = match listener.accept().await {
+            Ok((stream, _)) => stream,
+            Err(err) => {
+                log::error!("fail to accept a connection: {}", err);
+                continue;
+            }
+        };
+
+        let index = index.clone();
+        tokio::spawn(async move {
+            if let Err(err) = handle(stream, &index).await {
+                log::error!("fail to answer a client: {:?}", err);
+            }
+        });
+    }
+}
+
+/// Binds the socket, replacing the one left behind by a daemon that is no longer running.
+async fn bind(socket: &str) -> Result<UnixListener> {
+    if Path::new(socket).exists() {
+        if UnixStream::connect(socket).await.is_ok() {
+            bail!("a daemon is already listening on {}", socket);
+        }
+        std::fs::remove_file(socket).wrap_err("fail to remove the stale socket")?;
+    }
+
+    UnixListener::bind(socket).wrap_err_with(|| format!("fail to listen on {}", socket))
+}
+
+/// Checks if the filesystem `event` can change the candidates of `directory`.
+///
+/// Only folders being created, removed or renamed, marker files and ignore files matter, and
+/// only down to the depth where the scan reads them. Worktrees being added or removed always do.
+fn affects(directory: &Directory, event: &notify::Event) -> bool {
+    let structural = matches!(
+        event.kind,
+        EventKind::Create(_) | EventKind::Remove(_) | EventKind::Modify(ModifyKind::Name(_))
+    );
+    // Files coming and going in the watched folders, e.g. editor swap files, don't matter.
+    let file = matches!(
+        event.kind,
+        EventKind::Create(CreateKind::File) | EventKind::Remove(RemoveKind::File)
+    );
+
+    event.paths.iter().any(|path| {
+        let Ok(relative) = path.strip_prefix(&directory.path) else {
+            return false;
+        };
+        let name = path.file_name().unwrap_or_default();
+        let ignore_file = directory.gitignore && (name == ".gitignore" || name == ".ignore");
+        let marker = directory.markers.iter().any(|marker| name == marker.as_str());
+        // Renames don't tell files from folders, but files saved through a rename are files.
+        let renamed_file = matches!(event.kind, EventKind::Modify(_)) && path.is_file();
+        let folder = !(file || renamed_file);
+        let worktree =
+            directory.worktrees
+                && relative.components().collect::<Vec<_>>().windows(2).any(|pair| {
+                    pair[0].as_os_str() == ".git" && pair[1].as_os_str() == "worktrees"
+                });
+
+        worktree
+            || ((structural && (folder || marker)) || ignore_file)
+                && relative.components().count() <= directory.maxdepth + 1
+    })
+}
+
+fn scan(config: &Config, directory: &Directory) -> Scan {
+    directory.scan(&config.exclude).unwrap_or_else(|err| {
+        log::error!("fail to scan {}: {:?}", directory.path, err);
+        Scan::default()
+    })
+}
+
+/// Updates the `current` scan of `directory` with the `changes` of a burst of events.
+///
+/// Directories listing worktrees are scanned whole, since their worktrees can live anywhere.
+fn update(config: &Config, directory: &Directory, mut current: Scan, changes: &[PathBuf]) -> Scan {
+    if directory.worktrees {
+        return scan(config, directory);
+    }
+
+    for path in changes {
+        if let Err(err) = directory.rescan(&config.exclude, &mut current, path) {
+            log::error!("fail to rescan {}: {:?}", path.display(), err);
+            return scan(config, directory);
+        }
+    }
+
+    current
+}
+
+/// Filesystem watches of the daemon, one per folder whose entries matter.
+///
+/// Folders are watched on their own rather than recursively so the ones the scan skips, like
+/// `node_modules` or `.git`, don't use up the watches of the system or flood the daemon.
+struct Watches {
+    watcher: notify::RecommendedWatcher,
+    /// Folder of the configuration file.
+    config: Option<String>,
+    /// Folders to watch for every configured directory.
+    roots: Vec<Vec<String>>,
+    /// Folders currently watched.
+    watched: BTreeSet<String>,
+}
+
+impl Watches {
+    /// Watches the configuration file, the folders of the directories being added by
+    /// [`Watches::set`].
+    fn new(
+        config: &Config,
+        tx: mpsc::UnboundedSender<notify::Result<notify::Event>>,
+    ) -> Result<Self> {
+        let watcher = notify::recommended_watcher(move |event| {
+            let _ = tx.send(event);
+        })
+        .wrap_err("fail to create the filesystem watcher")?;
+
+        let mut watches = Self {
+            watcher,
+            config: Path::new(config.path())
+                .parent()
+                .filter(|parent| parent.exists())
+                .map(|parent| parent.to_string_lossy().to_string()),
+            roots: vec![Vec::new(); config.directories.len()],
+            watched: BTreeSet::new(),
+        };
+        watches.sync();
+
+        Ok(watches)
+    }
+
+    /// Replaces the folders watched for the configured directory at `index`.
+    fn set(&mut self, index: usize, folders: Vec<String>) {
+        self.roots[index] = folders;
+        self.sync();
+    }
+
+    /// Adds the watches of the new folders and removes the ones of the folders left out.
+    fn sync(&mut self) {
+        let wanted: BTreeSet<String> =
+            self.roots.iter().flatten().chain(self.config.iter()).cloned().collect();
+
+        for folder in self.watched.difference(&wanted) {
+            // Removed folders lose their watch on their own.
+            if let Err(err) = self.watcher.unwatch(Path::new(folder)) {
+                log::debug!("fail to unwatch {}: {}", folder, err);
+            }
+        }
+        self.watched.retain(|folder| wanted.contains(folder));
+
+        for folder in wanted {
+            if self.watched.contains(&folder) {
+                continue;
+            }
+            match self.watcher.watch(Path::new(&folder), RecursiveMode::NonRecursive) {
+                Ok(()) => {
+                    self.watched.insert(folder);
+                }
+                Err(err) => log::error!("fail to watch {}: {}", folder, err),
+            }
+        }
+    }
+}
+
+/// Scans every configured directory into `index` and watches the folders they read.
+async fn rebuild(config: &Config, index: &Mutex<Index>, watches: &mut Watches) -> Result<()> {
+    let scans = {
+        let config = config.clone();
+        tokio::task::spawn_blocking(move || {
+            config.directories.iter().map(|d| scan(&config, d)).collect::<Vec<_>>()
+        })
+        .await?
+    };
+
+    for (i, scan) in scans.iter().enumerate() {
+        watches.set(i, scan.folders.clone());
+    }
+
+    let mut index = index.lock().unwrap();
+    index.config = canonical(config.path());
+    index.roots = scans;
+    log::info!("Indexed {} directories", index.directories().len());
+
+    Ok(())
+}
+
+/// Runs the daemon until it gets killed.
+pub async fn run(config_path: &str) -> Result<()> {
+    let mut config = Config::load(config_path)?;
+    let index = Arc::new(Mutex::new(Index::default()));
+    let (tx, mut rx) = mpsc::unbounded_channel();
+    let mut watches = Watches::new(&config, tx.clone())?;
+    rebuild(&config, &index, &mut watches).await?;
+
+    let socket = socket();
+    let listener = bind(&socket).await?;
+    println!("Listening on {}", socket);
+    tokio::spawn(serve(listener, index.clone()));
+
+    let config_file = canonical(config_path);
+
+    while let Some(event) = rx.recv().await {
+        // Gather the burst of events caused by a single change.
+        let mut events = vec![event];
+        tokio::time::sleep(DEBOUNCE).await;
+        while let Ok(event) = rx.try_recv() {
+            events.push(event);
+        }
+        let events: Vec<notify::Event> = events
+            .into_iter()
+            .filter_map(|event| event.map_err(|err| log::error!("watch error: {}", err)).ok())
+            .collect();
+
+        let reload = events
+            .iter()
+            .flat_map(|e| e.paths.iter())
+            .any(|path| canonical(&path.to_string_lossy()) == config_file);
+        if reload {
+            log::info!("Reloading the configuration");
+            match Config::load(config_path) {
+                Ok(new) => config = new,
+                Err(err) => {
+                    log::error!("fail to reload the configuration: {:?}", err);
+                    continue;
+                }
+            }
+            drop(watches);
+            watches = Watches::new(&config, tx.clone())?;
+            rebuild(&config, &index, &mut watches).await?;
+            continue;
+        }
+
+        for (i, directory) in config.directories.iter().enumerate() {
+            // Paths of the burst that matter to `directory`, each rescanned once.
+            let changes: Vec<PathBuf> = events
+                .iter()
+                .filter(|event| affects(directory, event))
+                .flat_map(|event| event.paths.iter())
+                .filter(|path| path.starts_with(&directory.path))
+                .cloned()
+                .collect::<BTreeSet<_>>()
+                .into_iter()
+                .collect();
+            if changes.is_empty() {
+                continue;
+            }
+
+            log::debug!("Updating {} after {:?}", directory.path, changes);
+            let current = index.lock().unwrap().roots[i].clone();
+            let scan = {
+                let (config, directory) = (config.clone(), directory.clone());
+                tokio::task::spawn_blocking(move || update(&config, &directory, current, &changes))
+                    .await?
+            };
+            // Folders created since the last scan get watched from now on.
+            watches.set(i, scan.folders.clone());
+            index.lock().unwrap().roots[i] = scan;
+        }
+    }
+
+    Err(eyre!("the filesystem watcher stopped"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use notify::event::DataChange;
+
+    fn event(kind: EventKind, path: &str) -> notify::Event {
+        notify::Event::new(kind).add_path(path.into())
+    }
+
+    #[test]
+    fn only_relevant_events_trigger_a_rescan() {
+        let directory = Directory { path: "/src".into(), maxdepth: 2, ..Default::default() };
+        let create = EventKind::Create(CreateKind::Folder);
+        let write = EventKind::Modify(ModifyKind::Data(DataChange::Content));
+
+        assert!(affects(&directory, &event(create, "/src/a/b")));
+        assert!(affects(&directory, &event(create, "/src/a/b/.git")));
+        assert!(!affects(&directory, &event(create, "/src/a/b/c/d")));
+        assert!(!affects(&directory, &event(create, "/other/a")));
+        assert!(!affects(&directory, &event(write, "/src/a/.gitignore")));
+        let file = EventKind::Create(CreateKind::File);
+        assert!(!affects(&directory, &event(file, "/src/a/.main.rs.swp")));
+        let remove = EventKind::Remove(RemoveKind::Folder);
+        assert!(affects(&directory, &event(remove, "/src/a")));
+
+        let markers = Directory { markers: vec!["Cargo.toml".into()], ..directory.clone() };
+        assert!(affects(&markers, &event(file, "/src/a/Cargo.toml")));
+
+        let directory = Directory { gitignore: true, ..directory };
+        assert!(affects(&directory, &event(write, "/src/a/.gitignore")));
+
+        assert!(!affects(&directory, &event(create, "/src/a/b/.git/worktrees/c")));
+        let directory = Directory { worktrees: true, ..directory };
+        assert!(affects(&directory, &event(create, "/src/a/b/.git/worktrees/c")));
+    }
+
+    #[tokio::test]
+    async fn clients_get_the_index_of_their_configuration() {
+        let dir = tempfile::tempdir().unwrap();
+        let socket = dir.path().join("daemon.sock").to_string_lossy().to_string();
+        let config = Config::new(&dir.path().join("sessionizer.yaml").to_string_lossy());
+        config.save().unwrap();
+
+        let index = Index {
+            config: canonical(config.path()),
+            roots: vec![
+                Scan { directories: vec!["/b".into(), "/a".into()], ..Default::default() },
+                Scan { directories: vec!["/a".into()], ..Default::default() },
+            ],
+        };
+        tokio::spawn(serve(bind(&socket).await.unwrap(), Arc::new(Mutex::new(index))));
+
+        assert_eq!(query(&socket, &config).await.unwrap(), vec!["/a", "/b"]);
+        assert!(bind(&socket).await.is_err());
+
+        let other = Config::new(&dir.path().join("other.yaml").to_string_lossy());
+        assert!(query(&socket, &other).await.is_err());
+    }
+}
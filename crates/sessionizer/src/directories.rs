@@ -1,18 +1,286 @@
 use clap::{Parser, Subcommand};
-use color_eyre::eyre::Result;
+use color_eyre::eyre::{eyre, Result, WrapErr};
+use globset::{GlobBuilder, GlobSet, GlobSetBuilder};
+use ignore::gitignore::{Gitignore, GitignoreBuilder};
 use regex::Regex;
 use serde::{Deserialize, Serialize};
 use walkdir::WalkDir;
 
 use crate::config::Config;
 
-#[derive(Debug, Serialize, Deserialize)]
+mod cache;
+
+use cache::Cache;
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
 pub struct Directory {
     pub id: String,
     pub path: String,
     pub mindepth: usize,
     pub maxdepth: usize,
     pub grep: Option<String>,
+    /// Files or folders marking a project root, e.g. `.git` or `Cargo.toml`.
+    ///
+    /// When set, only directories containing one of them are candidates, and their
+    /// subdirectories are not scanned.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub markers: Vec<String>,
+    /// Globs of folders not to scan, e.g. `node_modules`, added to the global `exclude` list.
+    ///
+    /// Globs without a `/` match folder names at any depth, the others match paths relative to
+    /// `path`.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub exclude: Vec<String>,
+    /// Skip the folders ignored by the `.gitignore` and `.ignore` files found while scanning.
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub gitignore: bool,
+    /// List the linked worktrees of every git repository found as well.
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub worktrees: bool,
+    /// `KEY=value` entries that override the global `env` for sessions inside this directory.
+    #[serde(default)]
+    pub env: Vec<String>,
+    /// Windows and panes created for new sessions inside this directory.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub layout: Option<crate::layout::Layout>,
+    /// Lifecycle hooks of sessions inside this directory, run after the global ones.
+    #[serde(default, skip_serializing_if = "crate::hooks::Hooks::is_empty")]
+    pub hooks: crate::hooks::Hooks,
+    /// How the sessions inside this directory are named, overriding the global `naming`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub naming: Option<crate::naming::Naming>,
+}
+
+impl Directory {
+    /// Checks if `session` is this directory or lives inside it.
+    pub fn contains(&self, session: &str) -> bool {
+        std::path::Path::new(session).starts_with(&self.path)
+    }
+
+    /// Checks if `dir` contains one of the project `markers`.
+    fn is_project(&self, dir: &std::path::Path) -> bool {
+        self.markers.iter().any(|marker| dir.join(marker).exists())
+    }
+
+    /// Lists the candidate directories below `path`, skipping the `exclude` globs.
+    pub fn scan(&self, exclude: &[String]) -> Result<Scan> {
+        log::debug!("Scanning directory: {:?}", self);
+        let mut scan = Scan::default();
+        self.walk(exclude, std::path::Path::new(&self.path), None, &mut scan)?;
+
+        Ok(scan)
+    }
+
+    /// Updates `scan` after the `path` entry got created, removed or changed, reading again only
+    /// the folders it affects.
+    ///
+    /// Marker and ignore files change the whole folder containing them, other entries only
+    /// their own subtree. The linked worktrees of `scan` are left alone.
+    pub fn rescan(
+        &self,
+        exclude: &[String],
+        scan: &mut Scan,
+        path: &std::path::Path,
+    ) -> Result<()> {
+        let Some(parent) = path.parent().filter(|parent| parent.starts_with(&self.path)) else {
+            return Ok(());
+        };
+        let name = path.file_name().unwrap_or_default();
+        let ignore_file = self.gitignore && (name == ".gitignore" || name == ".ignore");
+        let marker = self.markers.iter().any(|marker| name == marker.as_str());
+        let only = (!ignore_file && !marker).then_some(path);
+
+        // The parent is walked again to decide if `path` is still read, with its ignore files.
+        let stale = |folder: &str| {
+            let folder = std::path::Path::new(folder);
+            folder == parent || folder.starts_with(only.unwrap_or(parent))
+        };
+        scan.directories.retain(|folder| !stale(folder));
+        scan.folders.retain(|folder| !stale(folder));
+        scan.mtimes.retain(|folder, _| !stale(folder));
+
+        log::debug!("Rescanning {} in {}", path.display(), self.path);
+        self.walk(exclude, parent, only, scan)
+    }
+
+    /// Scans the folders below `from` into `scan`, only descending into its `only` entry when
+    /// given.
+    fn walk(
+        &self,
+        exclude: &[String],
+        from: &std::path::Path,
+        only: Option<&std::path::Path>,
+        scan: &mut Scan,
+    ) -> Result<()> {
+        // Create a regular expression from `directory.grep`.
+        let grep = self.grep.as_deref().map(Regex::new).transpose()?;
+        let exclude = globs(exclude.iter().chain(self.exclude.iter()))?;
+
+        // Depth of `from` below `path`.
+        let offset =
+            from.strip_prefix(&self.path).map_or(0, |relative| relative.components().count());
+        if offset > self.maxdepth {
+            return Ok(());
+        }
+
+        // `.gitignore` and `.ignore` rules of the folders being scanned, with their depth.
+        let mut ignores: Vec<(usize, Gitignore)> = Vec::new();
+        if self.gitignore {
+            for (depth, dir) in from.ancestors().skip(1).take(offset).enumerate() {
+                ignores.push((offset - 1 - depth, gitignore(dir)));
+            }
+            ignores.reverse();
+        }
+        if offset > 0 && excluded(&exclude, &ignores, &self.path, from) {
+            return Ok(());
+        }
+
+        let mut entries =
+            WalkDir::new(from).max_depth(self.maxdepth - offset).sort_by_file_name().into_iter();
+        while let Some(entry) = entries.next() {
+            let Ok(entry) = entry else { continue };
+            if !entry.path().is_dir() {
+                continue;
+            }
+
+            if entry.depth() == 1 && only.is_some_and(|only| entry.path() != only) {
+                entries.skip_current_dir();
+                continue;
+            }
+
+            let depth = offset + entry.depth();
+            if entry.depth() > 0 {
+                ignores.retain(|(ignored, _)| *ignored < depth);
+                if excluded(&exclude, &ignores, &self.path, entry.path()) {
+                    entries.skip_current_dir();
+                    continue;
+                }
+            }
+
+            let path = entry.path().to_string_lossy();
+            let project =
+                depth >= self.mindepth && !self.markers.is_empty() && self.is_project(entry.path());
+
+            // The contents of the leaves and the project roots aren't read, so changes inside
+            // them don't affect the scan, unless they add or remove a marker.
+            let read = depth < self.maxdepth && !project;
+            let probed = !self.markers.is_empty() && depth >= self.mindepth;
+            if read || probed {
+                if let Some(mtime) = cache::mtime(&path) {
+                    scan.mtimes.insert(path.to_string(), mtime);
+                }
+                scan.folders.push(path.to_string());
+            }
+
+            if self.gitignore {
+                ignores.push((depth, gitignore(entry.path())));
+            }
+
+            if depth < self.mindepth {
+                continue;
+            }
+
+            if project {
+                // Nested folders belong to the project found here.
+                entries.skip_current_dir();
+            }
+
+            if (self.markers.is_empty() || project)
+                && grep.as_ref().map_or(true, |g| g.is_match(&path))
+            {
+                scan.directories.push(path.to_string());
+
+                if self.worktrees {
+                    self.expand(entry.path(), scan);
+                }
+            }
+        }
+
+        Ok(())
+    }
+}
+
+impl Directory {
+    /// Adds the linked worktrees of the `repo` candidate to `scan`.
+    fn expand(&self, repo: &std::path::Path, scan: &mut Scan) {
+        for worktree in crate::worktree::list(repo) {
+            scan.directories.push(worktree.to_string_lossy().to_string());
+        }
+
+        for dir in crate::worktree::watched(repo) {
+            let dir = dir.to_string_lossy();
+            if let Some(mtime) = cache::mtime(&dir) {
+                scan.mtimes.insert(dir.to_string(), mtime);
+                scan.folders.push(dir.to_string());
+            }
+        }
+    }
+}
+
+/// Result of scanning a [`Directory`].
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct Scan {
+    /// Candidate directories.
+    pub directories: Vec<String>,
+    /// Modification time of every folder read during the scan, used to invalidate the cache.
+    pub mtimes: std::collections::BTreeMap<String, u64>,
+    /// Folders whose entries decide the candidates, the ones read and the ones checked for
+    /// `markers`, watched by the daemon.
+    #[serde(skip)]
+    pub folders: Vec<String>,
+}
+
+fn globs<'a>(patterns: impl Iterator<Item = &'a String>) -> Result<GlobSet> {
+    let mut builder = GlobSetBuilder::new();
+    for pattern in patterns {
+        let glob = GlobBuilder::new(pattern.trim_end_matches('/'))
+            .literal_separator(true)
+            .build()
+            .wrap_err_with(|| format!("invalid exclude pattern {}", pattern))?;
+        builder.add(glob);
+    }
+
+    builder.build().wrap_err("fail to build the exclude patterns")
+}
+
+/// Loads the `.gitignore` and `.ignore` files of `dir`, reporting the invalid ones.
+fn gitignore(dir: &std::path::Path) -> Gitignore {
+    let mut builder = GitignoreBuilder::new(dir);
+    for name in [".gitignore", ".ignore"] {
+        let file = dir.join(name);
+        if file.is_file() {
+            if let Some(err) = builder.add(&file) {
+                log::warn!("fail to parse {}: {}", file.display(), err);
+            }
+        }
+    }
+
+    builder.build().unwrap_or_else(|err| {
+        log::warn!("fail to load the ignore files of {}: {}", dir.display(), err);
+        Gitignore::empty()
+    })
+}
+
+/// Checks if the `dir` folder below `root` matches an `exclude` glob or an ignore file.
+fn excluded(
+    exclude: &GlobSet,
+    ignores: &[(usize, Gitignore)],
+    root: &str,
+    dir: &std::path::Path,
+) -> bool {
+    let name = dir.file_name().unwrap_or_default();
+    let relative = dir.strip_prefix(root).unwrap_or(dir);
+    if exclude.is_match(name) || exclude.is_match(relative) {
+        return true;
+    }
+
+    // The innermost ignore file matching the folder decides, as with git.
+    ignores
+        .iter()
+        .rev()
+        .map(|(_, ignore)| ignore.matched(dir, true))
+        .find(|m| !m.is_none())
+        .is_some_and(|m| m.is_ignore())
 }
 
 #[derive(Debug, Subcommand)]
@@ -31,6 +299,21 @@ pub enum Commands {
         /// Grep a specific type of directory
         #[clap(short, long, default_value = ".*")]
         grep: Option<String>,
+        /// Environment variable to set on sessions inside the directory, as `KEY=value`.
+        #[clap(short, long)]
+        env: Vec<String>,
+        /// Only list directories containing this file or folder, e.g. `.git`.
+        #[clap(long = "marker")]
+        markers: Vec<String>,
+        /// Glob of folders not to scan, e.g. `node_modules`.
+        #[clap(short = 'x', long)]
+        exclude: Vec<String>,
+        /// Skip the folders ignored by `.gitignore` and `.ignore` files.
+        #[clap(long)]
+        gitignore: bool,
+        /// List the linked worktrees of the git repositories found.
+        #[clap(short, long)]
+        worktrees: bool,
     },
     /// Remove a directory to be tracked by sessionizer.
     #[clap(name = "remove")]
@@ -43,7 +326,11 @@ pub enum Commands {
     List,
     /// List all the directories available
     #[clap(name = "evaluate")]
-    Evaluate,
+    Evaluate {
+        /// Rescan every directory instead of reusing the cached results.
+        #[clap(short, long)]
+        refresh: bool,
+    },
 }
 
 #[derive(Debug, Parser)]
@@ -56,41 +343,46 @@ pub struct Cli {
 
 pub async fn run(config: Config, cli: Cli) -> Result<()> {
     match cli.command {
-        Commands::Add { path, mindepth, maxdepth, grep } => {
-            add(config, path, mindepth, maxdepth, grep).await
+        Commands::Add {
+            path,
+            mindepth,
+            maxdepth,
+            grep,
+            env,
+            markers,
+            exclude,
+            gitignore,
+            worktrees,
+        } => {
+            let directory = Directory {
+                id: uuid::Uuid::new_v4().to_string(),
+                path,
+                mindepth: mindepth.unwrap_or(1),
+                maxdepth: maxdepth.unwrap_or(1),
+                grep,
+                markers,
+                exclude,
+                gitignore,
+                worktrees,
+                env,
+                ..Default::default()
+            };
+            add(config, directory).await
         }
         Commands::Remove { id } => remove(config, id).await,
         Commands::List => list(config).await,
-        Commands::Evaluate => evaluate_cmd(config).await,
+        Commands::Evaluate { refresh } => evaluate_cmd(config, refresh).await,
     }
 }
 
-pub async fn add(
-    mut config: Config,
-    path: String,
-    mindepth: Option<usize>,
-    maxdepth: Option<usize>,
-    grep: Option<String>,
-) -> Result<()> {
-    let directory = Directory {
-        id: uuid::Uuid::new_v4().to_string(),
-        path,
-        mindepth: mindepth.unwrap_or(1),
-        maxdepth: maxdepth.unwrap_or(1),
-        grep,
-    };
-
-    config.directories.push(directory);
-
-    config.save()?;
+pub async fn add(mut config: Config, directory: Directory) -> Result<()> {
+    config.update(|config| config.directories.push(directory))?;
 
     Ok(())
 }
 
 pub async fn remove(mut config: Config, id: String) -> Result<()> {
-    config.directories.retain(|d| d.id != id);
-
-    config.save()?;
+    config.update(|config| config.directories.retain(|d| d.id != id))?;
 
     Ok(())
 }
@@ -101,28 +393,77 @@ pub async fn list(config: Config) -> Result<()> {
     Ok(())
 }
 
+/// Lists the candidate directories, asking the daemon first and scanning them if it isn't
+/// running.
+pub async fn candidates(config: &Config) -> Result<Vec<String>> {
+    if let Some(directories) = crate::daemon::directories(config).await {
+        return Ok(directories);
+    }
+
+    evaluate(config)
+}
+
+/// Lists the candidate directories of every configured directory, reusing cached scans.
 pub fn evaluate(config: &Config) -> Result<Vec<String>> {
-    let mut directories = Vec::new();
+    evaluate_with(config, false)
+}
+
+/// Lists the candidate directories, rescanning every directory if `refresh` is set.
+pub fn evaluate_with(config: &Config, refresh: bool) -> Result<Vec<String>> {
+    let ttl = config.cache_ttl.unwrap_or(cache::DEFAULT_TTL);
+    if ttl == 0 {
+        return scan_all(config, &mut Cache::default(), true);
+    }
 
+    let mut cache = Cache::load(&Cache::home()?);
+    let directories = scan_all(config, &mut cache, refresh)?;
+    if let Err(err) = cache.save() {
+        log::warn!("{:?}", err);
+    }
+
+    Ok(directories)
+}
+
+/// Scans the directories missing from `cache` in parallel and stores their results.
+fn scan_all(config: &Config, cache: &mut Cache, refresh: bool) -> Result<Vec<String>> {
+    let ttl = config.cache_ttl.unwrap_or(cache::DEFAULT_TTL);
+
+    let mut scans = Vec::with_capacity(config.directories.len());
     for directory in config.directories.iter() {
-        // Create a regular expression from `directory.grep`.
-        let grep = Regex::new(directory.grep.as_ref().unwrap())?;
+        let cached = match refresh {
+            true => None,
+            false => cache.get(directory, &config.exclude, ttl)?,
+        };
+        scans.push(cached);
+    }
 
-        log::debug!("Scanning directory: {:?}", directory);
-        for entry in WalkDir::new(&directory.path)
-            .min_depth(directory.mindepth)
-            .max_depth(directory.maxdepth)
+    let fresh = std::thread::scope(|scope| {
+        let handles: Vec<_> = config
+            .directories
+            .iter()
+            .zip(scans.iter())
+            .filter(|(_, cached)| cached.is_none())
+            .map(|(directory, _)| (directory, scope.spawn(|| directory.scan(&config.exclude))))
+            .collect();
+
+        handles
             .into_iter()
-            .filter_map(|e| {
-                // Values myst be `ok` and match `grep` regex
-                e.ok().filter(|e| grep.is_match(&e.path().to_string_lossy()))
+            .map(|(directory, handle)| {
+                let scan = handle.join().map_err(|_| eyre!("fail to scan {}", directory.path))?;
+                Ok((directory, scan?))
             })
-        {
-            if entry.path().is_dir() {
-                directories.push(entry.path().to_string_lossy().to_string());
-            }
-        }
+            .collect::<Result<Vec<_>>>()
+    })?;
+
+    let mut directories = Vec::new();
+    for scan in scans.into_iter().flatten() {
+        directories.extend(scan.directories);
+    }
+    for (directory, scan) in fresh {
+        directories.extend(scan.directories.iter().cloned());
+        cache.insert(directory, &config.exclude, scan)?;
     }
+    cache.retain(&config.directories, &config.exclude)?;
 
     let mut directories = directories
         .into_iter()
@@ -135,8 +476,221 @@ pub fn evaluate(config: &Config) -> Result<Vec<String>> {
     Ok(directories)
 }
 
-pub async fn evaluate_cmd(config: Config) -> Result<()> {
-    println!("{}", evaluate(&config)?.join("\n"));
+pub async fn evaluate_cmd(config: Config, refresh: bool) -> Result<()> {
+    println!("{}", evaluate_with(&config, refresh)?.join("\n"));
 
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tree(dir: &tempfile::TempDir, paths: &[&str]) -> String {
+        for path in paths {
+            let path = dir.path().join(path);
+            if path.extension().is_some() {
+                std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+                std::fs::write(path, "").unwrap();
+            } else {
+                std::fs::create_dir_all(path).unwrap();
+            }
+        }
+        dir.path().to_string_lossy().to_string()
+    }
+
+    #[test]
+    fn scan_respects_depth_and_grep() {
+        let dir = tempfile::tempdir().unwrap();
+        let root = tree(&dir, &["a/x", "b/y", "c.txt"]);
+        let directory =
+            Directory { path: root.clone(), mindepth: 1, maxdepth: 1, ..Default::default() };
+
+        assert_eq!(
+            directory.scan(&[]).unwrap().directories,
+            vec![format!("{root}/a"), format!("{root}/b")]
+        );
+
+        let directory = Directory { grep: Some("/a(/|$)".into()), maxdepth: 2, ..directory };
+        assert_eq!(
+            directory.scan(&[]).unwrap().directories,
+            vec![format!("{root}/a"), format!("{root}/a/x")]
+        );
+    }
+
+    #[test]
+    fn markers_select_project_roots_and_stop_descent() {
+        let dir = tempfile::tempdir().unwrap();
+        let root = tree(
+            &dir,
+            &["work/api/.git", "work/api/vendor/lib/Cargo.toml", "work/web/package.json", "notes"],
+        );
+        let directory = Directory {
+            path: root.clone(),
+            mindepth: 1,
+            maxdepth: 4,
+            markers: vec![".git".into(), "Cargo.toml".into(), "package.json".into()],
+            ..Default::default()
+        };
+
+        let scan = directory.scan(&[]).unwrap();
+        assert_eq!(scan.directories, vec![format!("{root}/work/api"), format!("{root}/work/web")]);
+        assert_eq!(
+            scan.folders,
+            vec![
+                root.clone(),
+                format!("{root}/notes"),
+                format!("{root}/work"),
+                format!("{root}/work/api"),
+                format!("{root}/work/web")
+            ]
+        );
+    }
+
+    #[test]
+    fn exclude_globs_skip_folders_and_their_content() {
+        let dir = tempfile::tempdir().unwrap();
+        let root = tree(&dir, &["app/node_modules/pkg", "app/src", "lib/target/debug", "web/dist"]);
+        let directory = Directory {
+            path: root.clone(),
+            mindepth: 1,
+            maxdepth: 3,
+            exclude: vec!["target".into(), "web/*".into()],
+            ..Default::default()
+        };
+
+        let scan = directory.scan(&["node_modules".into()]).unwrap();
+        assert_eq!(
+            scan.directories,
+            vec![
+                format!("{root}/app"),
+                format!("{root}/app/src"),
+                format!("{root}/lib"),
+                format!("{root}/web")
+            ]
+        );
+        assert_eq!(
+            scan.folders,
+            vec![
+                root.clone(),
+                format!("{root}/app"),
+                format!("{root}/app/src"),
+                format!("{root}/lib"),
+                format!("{root}/web")
+            ]
+        );
+    }
+
+    #[test]
+    fn ignore_files_are_honored_when_enabled() {
+        let dir = tempfile::tempdir().unwrap();
+        let root = tree(&dir, &["repo/build", "repo/keep", "repo/src/generated", "repo/src/main"]);
+        std::fs::write(dir.path().join("repo/.gitignore"), "build/\n").unwrap();
+        std::fs::write(dir.path().join("repo/src/.ignore"), "generated\n").unwrap();
+        let directory = Directory {
+            path: format!("{root}/repo"),
+            mindepth: 1,
+            maxdepth: 2,
+            ..Default::default()
+        };
+
+        assert_eq!(directory.scan(&[]).unwrap().directories.len(), 5);
+
+        let directory = Directory { gitignore: true, ..directory };
+        assert_eq!(
+            directory.scan(&[]).unwrap().directories,
+            vec![
+                format!("{root}/repo/keep"),
+                format!("{root}/repo/src"),
+                format!("{root}/repo/src/main")
+            ]
+        );
+    }
+
+    #[test]
+    fn rescans_match_full_scans() {
+        let dir = tempfile::tempdir().unwrap();
+        let root = tree(&dir, &["work/api/Cargo.toml", "work/api/src", "work/tools", "notes"]);
+        let directory = Directory {
+            path: root.clone(),
+            mindepth: 1,
+            maxdepth: 3,
+            markers: vec!["Cargo.toml".into()],
+            gitignore: true,
+            ..Default::default()
+        };
+        let mut scan = directory.scan(&[]).unwrap();
+        let sorted = |scan: &Scan| {
+            let (mut directories, mut folders) = (scan.directories.clone(), scan.folders.clone());
+            directories.sort();
+            folders.sort();
+            (directories, folders, scan.mtimes.keys().cloned().collect::<Vec<_>>())
+        };
+
+        let mut check = |change: &str| {
+            directory.rescan(&[], &mut scan, &dir.path().join(change)).unwrap();
+            assert_eq!(sorted(&scan), sorted(&directory.scan(&[]).unwrap()), "{}", change);
+        };
+
+        std::fs::create_dir_all(dir.path().join("work/web/src")).unwrap();
+        check("work/web");
+        std::fs::remove_dir(dir.path().join("notes")).unwrap();
+        check("notes");
+        std::fs::write(dir.path().join("work/tools/Cargo.toml"), "").unwrap();
+        check("work/tools/Cargo.toml");
+        std::fs::remove_file(dir.path().join("work/api/Cargo.toml")).unwrap();
+        check("work/api/Cargo.toml");
+        std::fs::write(dir.path().join("work/.gitignore"), "api/\n").unwrap();
+        check("work/.gitignore");
+        assert_eq!(scan.directories, vec![format!("{root}/work/tools")]);
+    }
+
+    #[test]
+    fn scan_all_merges_the_roots_and_caches_them() {
+        let dir = tempfile::tempdir().unwrap();
+        let root = tree(&dir, &["one/a", "two/b", "two/c"]);
+        let mut config = Config::new("sessionizer.yaml");
+        for name in ["one", "two", "one"] {
+            let path = format!("{root}/{name}");
+            config.directories.push(Directory {
+                path,
+                mindepth: 1,
+                maxdepth: 1,
+                ..Default::default()
+            });
+        }
+        let mut cache = Cache::load(&dir.path().join("cache.json").to_string_lossy());
+
+        let expected =
+            vec![format!("{root}/one/a"), format!("{root}/two/b"), format!("{root}/two/c")];
+        assert_eq!(scan_all(&config, &mut cache, false).unwrap(), expected);
+        assert_eq!(scan_all(&config, &mut cache, false).unwrap(), expected);
+
+        std::fs::create_dir(dir.path().join("one/d")).unwrap();
+        assert_eq!(scan_all(&config, &mut cache, false).unwrap().len(), 4);
+    }
+
+    #[test]
+    fn worktrees_of_candidate_repositories_are_listed() {
+        let dir = tempfile::tempdir().unwrap();
+        let root = tree(&dir, &["src/api/.git", "src/web", "trees"]);
+        let worktree = dir.path().join("trees/api-login");
+        crate::worktree::tests::add(&dir.path().join("src/api"), &worktree, "login");
+        let directory = Directory {
+            path: format!("{root}/src"),
+            mindepth: 1,
+            maxdepth: 1,
+            worktrees: true,
+            ..Default::default()
+        };
+
+        assert_eq!(
+            directory.scan(&[]).unwrap().directories,
+            vec![
+                format!("{root}/src/api"),
+                format!("{root}/trees/api-login"),
+                format!("{root}/src/web")
+            ]
+        );
+    }
+}
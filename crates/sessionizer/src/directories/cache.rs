@@ -0,0 +1,218 @@
+use std::collections::BTreeMap;
+use std::time::UNIX_EPOCH;
+
+use color_eyre::eyre::{Result, WrapErr};
+use serde::{Deserialize, Serialize};
+
+use super::{Directory, Scan};
+use crate::state::now;
+
+/// Seconds a scan is reused when no `cache_ttl` is configured.
+pub const DEFAULT_TTL: u64 = 60 * 60;
+
+/// Results of previous directory scans, stored under `$XDG_CACHE_HOME/sessionizer`.
+///
+/// A scan is reused until it expires or one of the folders it read was modified, which happens
+/// whenever a folder gets created, removed or renamed inside it.
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct Cache {
+    #[serde(default)]
+    entries: BTreeMap<String, Entry>,
+    #[serde(skip)]
+    path: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct Entry {
+    /// Unix timestamp of the scan, in seconds.
+    scanned: u64,
+    scan: Scan,
+}
+
+/// Options that change the result of a scan.
+#[derive(Serialize)]
+struct Key<'a> {
+    path: &'a str,
+    mindepth: usize,
+    maxdepth: usize,
+    grep: &'a Option<String>,
+    markers: &'a [String],
+    exclude: &'a [String],
+    directory_exclude: &'a [String],
+    gitignore: bool,
+    worktrees: bool,
+}
+
+fn key(directory: &Directory, exclude: &[String]) -> Result<String> {
+    let key = Key {
+        path: &directory.path,
+        mindepth: directory.mindepth,
+        maxdepth: directory.maxdepth,
+        grep: &directory.grep,
+        markers: &directory.markers,
+        exclude,
+        directory_exclude: &directory.exclude,
+        gitignore: directory.gitignore,
+        worktrees: directory.worktrees,
+    };
+
+    Ok(crate::project::hash(&serde_json::to_string(&key)?))
+}
+
+/// Modification time of `path` in nanoseconds, if it still exists.
+pub fn mtime(path: &str) -> Option<u64> {
+    let modified = std::fs::metadata(path).and_then(|m| m.modified()).ok()?;
+    modified.duration_since(UNIX_EPOCH).ok().map(|d| d.as_nanos() as u64)
+}
+
+impl Cache {
+    /// Loads the cache file, starting over if it's missing or unreadable.
+    pub fn load(path: &str) -> Self {
+        let cache = std::fs::read_to_string(path)
+            .ok()
+            .and_then(|json| match serde_json::from_str::<Self>(&json) {
+                Ok(cache) => Some(cache),
+                Err(err) => {
+                    log::warn!("fail to deserialize the cache {}: {}", path, err);
+                    None
+                }
+            })
+            .unwrap_or_default();
+
+        Self { path: path.to_string(), ..cache }
+    }
+
+    pub fn save(&self) -> Result<()> {
+        if let Some(parent) = std::path::Path::new(&self.path).parent() {
+            std::fs::create_dir_all(parent).wrap_err("fail to create the cache directory")?;
+        }
+
+        let json = serde_json::to_string(&self).wrap_err("fail to serialize the cache")?;
+
+        crate::files::write_atomic(&self.path, json.as_bytes()).wrap_err("fail to save the cache")
+    }
+
+    /// Returns the scan of `directory` if it's younger than `ttl` seconds and none of the
+    /// folders it read changed since.
+    pub fn get(&self, directory: &Directory, exclude: &[String], ttl: u64) -> Result<Option<Scan>> {
+        let Some(entry) = self.entries.get(&key(directory, exclude)?) else {
+            return Ok(None);
+        };
+
+        if now().saturating_sub(entry.scanned) >= ttl {
+            log::debug!("Cached scan of {} expired", directory.path);
+            return Ok(None);
+        }
+
+        if let Some((path, _)) =
+            entry.scan.mtimes.iter().find(|(path, modified)| mtime(path) != Some(**modified))
+        {
+            log::debug!("Cached scan of {} is stale, {} changed", directory.path, path);
+            return Ok(None);
+        }
+
+        Ok(Some(entry.scan.clone()))
+    }
+
+    pub fn insert(&mut self, directory: &Directory, exclude: &[String], scan: Scan) -> Result<()> {
+        self.entries.insert(key(directory, exclude)?, Entry { scanned: now(), scan });
+
+        Ok(())
+    }
+
+    /// Drops the scans of roots that are no longer configured.
+    pub fn retain(&mut self, directories: &[Directory], exclude: &[String]) -> Result<()> {
+        let keys = directories.iter().map(|d| key(d, exclude)).collect::<Result<Vec<_>>>()?;
+        self.entries.retain(|key, _| keys.contains(key));
+
+        Ok(())
+    }
+
+    /// Default cache file path, inside `$XDG_CACHE_HOME/sessionizer`.
+    pub fn home() -> Result<String> {
+        let dir = match std::env::var("XDG_CACHE_HOME") {
+            Ok(dir) if !dir.is_empty() => dir,
+            _ => format!("{}/.cache", std::env::var("HOME")?),
+        };
+
+        Ok(format!("{}/sessionizer/directories.json", dir))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn setup(dir: &tempfile::TempDir) -> (Cache, Directory) {
+        std::fs::create_dir_all(dir.path().join("root/a")).unwrap();
+        let cache = Cache::load(&dir.path().join("cache.json").to_string_lossy());
+        let directory = Directory {
+            path: dir.path().join("root").to_string_lossy().to_string(),
+            mindepth: 1,
+            maxdepth: 2,
+            ..Default::default()
+        };
+        (cache, directory)
+    }
+
+    #[test]
+    fn scans_are_reused_until_a_folder_changes() {
+        let dir = tempfile::tempdir().unwrap();
+        let (mut cache, directory) = setup(&dir);
+
+        cache.insert(&directory, &[], directory.scan(&[]).unwrap()).unwrap();
+        cache.save().unwrap();
+
+        let cache = Cache::load(&cache.path);
+        let scan = cache.get(&directory, &[], DEFAULT_TTL).unwrap().unwrap();
+        assert_eq!(scan.directories, vec![format!("{}/a", directory.path)]);
+
+        std::fs::create_dir(dir.path().join("root/a/b")).unwrap();
+        assert!(cache.get(&directory, &[], DEFAULT_TTL).unwrap().is_none());
+    }
+
+    #[test]
+    fn changes_inside_the_candidates_keep_the_scans() {
+        let dir = tempfile::tempdir().unwrap();
+        let (mut cache, directory) = setup(&dir);
+        std::fs::create_dir_all(dir.path().join("root/a/lib")).unwrap();
+        std::fs::create_dir_all(dir.path().join("root/app/src")).unwrap();
+        std::fs::write(dir.path().join("root/app/Cargo.toml"), "").unwrap();
+        let leaves = Directory { maxdepth: 1, ..directory.clone() };
+        let projects = Directory { markers: vec!["Cargo.toml".into()], ..directory };
+
+        cache.insert(&leaves, &[], leaves.scan(&[]).unwrap()).unwrap();
+        cache.insert(&projects, &[], projects.scan(&[]).unwrap()).unwrap();
+        std::fs::write(dir.path().join("root/app/src/main.rs"), "").unwrap();
+        assert!(cache.get(&projects, &[], DEFAULT_TTL).unwrap().is_some());
+
+        std::fs::write(dir.path().join("root/a/notes.md"), "").unwrap();
+        assert!(cache.get(&leaves, &[], DEFAULT_TTL).unwrap().is_some());
+        assert!(cache.get(&projects, &[], DEFAULT_TTL).unwrap().is_none());
+
+        // Adding or removing a marker turns a folder into a project or back.
+        cache.insert(&projects, &[], projects.scan(&[]).unwrap()).unwrap();
+        std::fs::write(dir.path().join("root/a/lib/Cargo.toml"), "").unwrap();
+        assert!(cache.get(&projects, &[], DEFAULT_TTL).unwrap().is_none());
+
+        cache.insert(&projects, &[], projects.scan(&[]).unwrap()).unwrap();
+        std::fs::remove_file(dir.path().join("root/app/Cargo.toml")).unwrap();
+        assert!(cache.get(&projects, &[], DEFAULT_TTL).unwrap().is_none());
+    }
+
+    #[test]
+    fn scans_expire_and_depend_on_the_options() {
+        let dir = tempfile::tempdir().unwrap();
+        let (mut cache, directory) = setup(&dir);
+
+        cache.insert(&directory, &[], directory.scan(&[]).unwrap()).unwrap();
+
+        assert!(cache.get(&directory, &[], 0).unwrap().is_none());
+        assert!(cache.get(&directory, &["a".into()], DEFAULT_TTL).unwrap().is_none());
+        let deeper = Directory { maxdepth: 3, ..directory.clone() };
+        assert!(cache.get(&deeper, &[], DEFAULT_TTL).unwrap().is_none());
+
+        cache.retain(&[deeper], &[]).unwrap();
+        assert!(cache.get(&directory, &[], DEFAULT_TTL).unwrap().is_none());
+    }
+}
@@ -0,0 +1,132 @@
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+use color_eyre::eyre::{eyre, Result, WrapErr};
+use fs4::FileExt;
+
+/// Exclusive advisory lock on the `<path>.lock` file, released when dropped.
+///
+/// The lock lives on a sibling file so it survives `path` being replaced by [`write_atomic`].
+#[derive(Debug)]
+pub struct Lock {
+    file: std::fs::File,
+}
+
+impl Lock {
+    /// Blocks until the lock for `path` is acquired.
+    pub fn acquire(path: &str) -> Result<Self> {
+        let path = lock_path(path);
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent)
+                .wrap_err_with(|| format!("fail to create {}", parent.display()))?;
+        }
+
+        let file = std::fs::OpenOptions::new()
+            .create(true)
+            .truncate(false)
+            .write(true)
+            .open(&path)
+            .wrap_err_with(|| format!("fail to open lock file {}", path.display()))?;
+
+        log::debug!("Locking {}", path.display());
+        FileExt::lock_exclusive(&file)
+            .wrap_err_with(|| format!("fail to lock {}", path.display()))?;
+
+        Ok(Self { file })
+    }
+}
+
+impl Drop for Lock {
+    fn drop(&mut self) {
+        if let Err(err) = FileExt::unlock(&self.file) {
+            log::error!("fail to release lock: {}", err);
+        }
+    }
+}
+
+fn lock_path(path: &str) -> PathBuf {
+    PathBuf::from(format!("{}.lock", path))
+}
+
+/// Replaces the contents of `path` by writing them to a temporary file in the same directory and
+/// renaming it over `path`, so readers never see a partially written file.
+///
+/// A symlinked `path` is resolved first, so the file it points to is replaced instead of the link.
+pub fn write_atomic(path: &str, contents: &[u8]) -> Result<()> {
+    let target = std::fs::canonicalize(path).unwrap_or_else(|_| PathBuf::from(path));
+    let target = target.as_path();
+    let dir = match target.parent() {
+        Some(dir) if !dir.as_os_str().is_empty() => dir,
+        _ => Path::new("."),
+    };
+    let name = target.file_name().ok_or_else(|| eyre!("invalid file path {}", path))?;
+    let tmp = dir.join(format!(".{}.{}.tmp", name.to_string_lossy(), uuid::Uuid::new_v4()));
+
+    let result = (|| {
+        let mut file = std::fs::File::create(&tmp)?;
+        file.write_all(contents)?;
+        file.sync_all()?;
+        std::fs::rename(&tmp, target)
+    })();
+
+    if result.is_err() {
+        let _ = std::fs::remove_file(&tmp);
+    }
+
+    result.wrap_err_with(|| format!("fail to write {}", path))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn write_atomic_replaces_the_file_without_leftovers() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("file").to_string_lossy().to_string();
+
+        write_atomic(&path, b"one").unwrap();
+        write_atomic(&path, b"two").unwrap();
+
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), "two");
+        assert_eq!(std::fs::read_dir(dir.path()).unwrap().count(), 1);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn write_atomic_follows_symlinks() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::create_dir(dir.path().join("dotfiles")).unwrap();
+        let file = dir.path().join("dotfiles/file");
+        let link = dir.path().join("link");
+        std::fs::write(&file, "one").unwrap();
+        std::os::unix::fs::symlink(&file, &link).unwrap();
+
+        write_atomic(&link.to_string_lossy(), b"two").unwrap();
+
+        assert!(std::fs::symlink_metadata(&link).unwrap().file_type().is_symlink());
+        assert_eq!(std::fs::read_to_string(&file).unwrap(), "two");
+        assert_eq!(std::fs::read_dir(dir.path().join("dotfiles")).unwrap().count(), 1);
+    }
+
+    #[test]
+    fn lock_serializes_critical_sections() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("counter").to_string_lossy().to_string();
+        write_atomic(&path, b"0").unwrap();
+
+        std::thread::scope(|scope| {
+            for _ in 0..8 {
+                scope.spawn(|| {
+                    for _ in 0..10 {
+                        let _lock = Lock::acquire(&path).unwrap();
+                        let value: u32 = std::fs::read_to_string(&path).unwrap().parse().unwrap();
+                        write_atomic(&path, (value + 1).to_string().as_bytes()).unwrap();
+                    }
+                });
+            }
+        });
+
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), "80");
+    }
+}
@@ -1,61 +1,59 @@
 use color_eyre::eyre::{eyre, OptionExt, Result, WrapErr};
 use tokio::io::AsyncWriteExt;
 
-use crate::config::Config;
-
-pub async fn sessions(sessions: Vec<String>) -> Result<String> {
-    let mut fzf = tokio::process::Command::new("fzf")
-        .args([
-            "--header",
-            "Press CTRL-X to delete a session.",
-            "--bind",
-            "ctrl-x:execute-silent(sessionizer sessions remove {+})+reload(sessionizer sessions list)"
-        ])
-        .stdout(std::process::Stdio::piped())
-        .stdin(std::process::Stdio::piped())
-        .spawn()
-        .wrap_err("Failed to spawn fzf")?;
-
-    let mut stdin = fzf.stdin.take().ok_or_eyre("fail to take stdin")?;
-    tokio::spawn(async move {
-        stdin.write_all(sessions.join("\n").as_bytes()).await.expect("fail to write to stdin");
-        drop(stdin);
-    });
-
-    // wait for the process to complete
-    let fzf = fzf.wait_with_output().await?;
-
-    // Bail if the status of fzf was an error
-    if !fzf.status.success() {
-        Err(eyre!("fzf error"))
-    } else {
-        Ok(String::from_utf8(fzf.stdout)?)
+use crate::picker::{Request, Selection};
+
+/// Runs an fzf compatible `program`, such as `fzf` or `sk`, and returns the selected items.
+///
+/// Spawn errors are returned as [`std::io::Error`] so callers can tell a missing binary apart.
+pub async fn run(program: &str, request: Request) -> Result<Selection> {
+    let mut args = vec!["--header".to_string(), request.header];
+    if request.multi {
+        args.push("--multi".into());
+    }
+    if let Some(preview) = request.preview {
+        args.extend(["--preview".into(), preview]);
+    }
+    for binding in request.bindings {
+        let mut action = format!("{}:execute-silent({})", binding.key, binding.command);
+        if let Some(reload) = binding.reload {
+            action.push_str(&format!("+reload({})", reload));
+        }
+        args.extend(["--bind".into(), action]);
+    }
+    if !request.expect.is_empty() {
+        args.push(format!("--expect={}", request.expect.join(",")));
     }
-}
-
-pub async fn directories(config: &Config) -> Result<String> {
-    let dirs = crate::directories::evaluate(config)?;
 
-    let mut fzf = tokio::process::Command::new("fzf")
-        .args(["--header", "Select a directory from the list to start a new session"])
+    log::debug!("$ {} {:?}", program, args);
+    let mut fzf = tokio::process::Command::new(program)
+        .args(&args)
         .stdout(std::process::Stdio::piped())
         .stdin(std::process::Stdio::piped())
-        .spawn()
-        .wrap_err("Failed to spawn fzf")?;
+        .spawn()?;
 
     let mut stdin = fzf.stdin.take().ok_or_eyre("fail to take stdin")?;
+    let items = request.items.join("\n");
     tokio::spawn(async move {
-        stdin.write_all(dirs.join("\n").as_bytes()).await.expect("fail to write to stdin");
+        stdin.write_all(items.as_bytes()).await.expect("fail to write to stdin");
         drop(stdin);
     });
 
     // wait for the process to complete
-    let fzf = fzf.wait_with_output().await?;
+    let fzf =
+        fzf.wait_with_output().await.wrap_err_with(|| format!("fail to wait for {}", program))?;
 
     // Bail if the status of fzf was an error
     if !fzf.status.success() {
-        Err(eyre!("fzf error"))
+        Err(eyre!("{} error", program))
     } else {
-        Ok(String::from_utf8(fzf.stdout)?)
+        let stdout = String::from_utf8(fzf.stdout)?;
+        let mut lines = stdout.lines().map(String::from);
+        // With `--expect`, the first line holds the key used to accept the selection.
+        let key = match request.expect.is_empty() {
+            true => None,
+            false => lines.next().filter(|key| !key.is_empty()),
+        };
+        Ok(Selection { key, items: lines.collect() })
     }
 }
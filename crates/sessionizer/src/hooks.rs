@@ -0,0 +1,206 @@
+use std::time::Duration;
+
+use color_eyre::eyre::{eyre, Result, WrapErr};
+use serde::{Deserialize, Serialize};
+
+/// Seconds a hook command may run when no `timeout` is configured.
+const DEFAULT_TIMEOUT: u64 = 10;
+
+/// Shell commands run on session lifecycle events.
+///
+/// Every command runs with `sh -c` inside the session directory, and receives the
+/// `SESSIONIZER_EVENT`, `SESSIONIZER_SESSION`, `SESSIONIZER_PATH` and `SESSIONIZER_PREVIOUS`
+/// environment variables.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct Hooks {
+    /// Run after a session is created.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub on_create: Vec<String>,
+    /// Run after the client moves to a session.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub on_enter: Vec<String>,
+    /// Run before the client moves away from a session.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub on_leave: Vec<String>,
+    /// Run after a session is killed.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub on_kill: Vec<String>,
+    /// Seconds each command may run before it gets killed.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub timeout: Option<u64>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Event {
+    Create,
+    Enter,
+    Leave,
+    Kill,
+}
+
+impl std::fmt::Display for Event {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Event::Create => write!(f, "on_create"),
+            Event::Enter => write!(f, "on_enter"),
+            Event::Leave => write!(f, "on_leave"),
+            Event::Kill => write!(f, "on_kill"),
+        }
+    }
+}
+
+impl Hooks {
+    pub fn is_empty(&self) -> bool {
+        self.on_create.is_empty()
+            && self.on_enter.is_empty()
+            && self.on_leave.is_empty()
+            && self.on_kill.is_empty()
+            && self.timeout.is_none()
+    }
+
+    /// Appends the commands of `other`, whose `timeout` takes precedence if set.
+    pub fn extend(&mut self, other: &Hooks) {
+        self.on_create.extend(other.on_create.iter().cloned());
+        self.on_enter.extend(other.on_enter.iter().cloned());
+        self.on_leave.extend(other.on_leave.iter().cloned());
+        self.on_kill.extend(other.on_kill.iter().cloned());
+        self.timeout = other.timeout.or(self.timeout);
+    }
+
+    pub fn commands(&self, event: Event) -> &[String] {
+        match event {
+            Event::Create => &self.on_create,
+            Event::Enter => &self.on_enter,
+            Event::Leave => &self.on_leave,
+            Event::Kill => &self.on_kill,
+        }
+    }
+}
+
+/// Session a hook is run for.
+#[derive(Debug, Clone, Copy)]
+pub struct Context<'a> {
+    /// Tmux session name.
+    pub session: &'a str,
+    /// Session directory.
+    pub path: &'a str,
+    /// Directory of the session the client was on before, if any.
+    pub previous: Option<&'a str>,
+}
+
+/// Runs the commands registered for `event`.
+///
+/// Failures are reported on stderr but never returned, so a broken hook can't prevent moving
+/// between sessions.
+pub async fn run(hooks: &Hooks, event: Event, context: Context<'_>) {
+    let timeout = Duration::from_secs(hooks.timeout.unwrap_or(DEFAULT_TIMEOUT));
+
+    for command in hooks.commands(event) {
+        if let Err(err) = run_command(command, event, context, timeout).await {
+            log::error!("{} hook failed: {:?}", event, err);
+            eprintln!("{} hook `{}` failed: {}", event, command, err);
+        }
+    }
+}
+
+async fn run_command(
+    command: &str,
+    event: Event,
+    context: Context<'_>,
+    timeout: Duration,
+) -> Result<()> {
+    log::debug!("$ sh -c {:?}", command);
+    let mut cmd = tokio::process::Command::new("sh");
+    cmd.args(["-c", command])
+        .env("SESSIONIZER_EVENT", event.to_string())
+        .env("SESSIONIZER_SESSION", context.session)
+        .env("SESSIONIZER_PATH", context.path)
+        .env("SESSIONIZER_PREVIOUS", context.previous.unwrap_or_default())
+        .stdin(std::process::Stdio::null())
+        .kill_on_drop(true);
+
+    if std::path::Path::new(context.path).is_dir() {
+        cmd.current_dir(context.path);
+    }
+
+    let mut child = cmd.spawn().wrap_err("fail to spawn sh")?;
+
+    match tokio::time::timeout(timeout, child.wait()).await {
+        Ok(status) => {
+            let status = status.wrap_err("fail to wait for the hook")?;
+            if !status.success() {
+                return Err(eyre!("exited with status: {}", status));
+            }
+            Ok(())
+        }
+        Err(_) => {
+            child.kill().await.wrap_err("fail to kill the hook")?;
+            Err(eyre!("timed out after {}s", timeout.as_secs()))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn context(path: &str) -> Context<'_> {
+        Context { session: "name", path, previous: Some("/previous") }
+    }
+
+    #[tokio::test]
+    async fn hooks_receive_the_session_context() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().to_string_lossy().to_string();
+        let hooks = Hooks {
+            on_enter: vec![
+                "echo \"$SESSIONIZER_EVENT $SESSIONIZER_SESSION $SESSIONIZER_PREVIOUS $PWD\" > out"
+                    .into(),
+            ],
+            ..Default::default()
+        };
+
+        run(&hooks, Event::Enter, context(&path)).await;
+
+        let out = std::fs::read_to_string(dir.path().join("out")).unwrap();
+        let pwd = std::fs::canonicalize(dir.path()).unwrap();
+        assert_eq!(out.trim(), format!("on_enter name /previous {}", pwd.display()));
+    }
+
+    #[tokio::test]
+    async fn failing_hooks_do_not_stop_the_next_ones() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().to_string_lossy().to_string();
+        let hooks =
+            Hooks { on_kill: vec!["exit 1".into(), "touch out".into()], ..Default::default() };
+
+        run(&hooks, Event::Kill, context(&path)).await;
+
+        assert!(dir.path().join("out").exists());
+    }
+
+    #[tokio::test]
+    async fn slow_hooks_time_out() {
+        let start = std::time::Instant::now();
+
+        let err = run_command("sleep 5", Event::Create, context("/"), Duration::from_millis(100))
+            .await
+            .unwrap_err();
+
+        assert!(err.to_string().contains("timed out"));
+        assert!(start.elapsed() < Duration::from_secs(5));
+    }
+
+    #[test]
+    fn extend_appends_commands_and_overrides_the_timeout() {
+        let mut hooks =
+            Hooks { on_enter: vec!["a".into()], timeout: Some(5), ..Default::default() };
+
+        hooks.extend(&Hooks { on_enter: vec!["b".into()], ..Default::default() });
+        assert_eq!(hooks.on_enter, vec!["a", "b"]);
+        assert_eq!(hooks.timeout, Some(5));
+
+        hooks.extend(&Hooks { timeout: Some(1), ..Default::default() });
+        assert_eq!(hooks.timeout, Some(1));
+    }
+}
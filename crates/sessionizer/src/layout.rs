@@ -0,0 +1,180 @@
+use std::path::Path;
+
+use color_eyre::eyre::Result;
+use serde::{Deserialize, Serialize};
+
+use crate::tmux::Multiplexer;
+
+/// Windows and panes created along with a new session.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct Layout {
+    pub windows: Vec<Window>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct Window {
+    /// Window name.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
+    /// Working directory, relative to the session directory.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub cwd: Option<String>,
+    /// Tmux layout applied once every pane is created, e.g. `main-vertical`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub layout: Option<String>,
+    /// Panes of the window. The first one is the pane the window is created with.
+    #[serde(default)]
+    pub panes: Vec<Pane>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct Pane {
+    /// Working directory, relative to the window directory.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub cwd: Option<String>,
+    /// Command typed into the pane once it's created.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub command: Option<String>,
+    /// How the pane is split from the previous one. Ignored for the first pane.
+    #[serde(default)]
+    pub split: Split,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Split {
+    /// New pane below the previous one.
+    #[default]
+    Vertical,
+    /// New pane to the right of the previous one.
+    Horizontal,
+}
+
+/// Resolves `cwd` relative to `base`, expanding `~`.
+fn resolve(base: &str, cwd: Option<&str>) -> String {
+    match cwd {
+        Some(cwd) => {
+            Path::new(base).join(shellexpand::tilde(cwd).as_ref()).to_string_lossy().into()
+        }
+        None => base.to_string(),
+    }
+}
+
+/// Builds the `layout` windows on a session rooted at `root` whose initial pane is `pane`.
+///
+/// The first window reuses the window the session was created with, and every other one is
+/// appended after it.
+pub async fn apply(
+    mux: &(impl Multiplexer + ?Sized),
+    session: &str,
+    root: &str,
+    pane: &str,
+    layout: &Layout,
+) -> Result<()> {
+    for (index, window) in layout.windows.iter().enumerate() {
+        let cwd = resolve(root, window.cwd.as_deref());
+        let mut panes = window.panes.iter();
+        let first = panes.next();
+        let first_cwd = resolve(&cwd, first.and_then(|p| p.cwd.as_deref()));
+
+        let target = if index == 0 {
+            if let Some(name) = &window.name {
+                mux.rename_window(pane, name).await?;
+            }
+            if first_cwd != root {
+                mux.send_keys(pane, &format!("cd {}", shell_quote(&first_cwd))).await?;
+            }
+            pane.to_string()
+        } else {
+            mux.new_window(session, window.name.as_deref(), &first_cwd).await?
+        };
+
+        if let Some(command) = first.and_then(|p| p.command.as_deref()) {
+            mux.send_keys(&target, command).await?;
+        }
+
+        for pane in panes {
+            let id =
+                mux.split_window(&target, pane.split, &resolve(&cwd, pane.cwd.as_deref())).await?;
+            if let Some(command) = &pane.command {
+                mux.send_keys(&id, command).await?;
+            }
+        }
+
+        if let Some(name) = &window.layout {
+            mux.select_layout(&target, name).await?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Quotes `value` so the shell reads it as a single word.
+pub(crate) fn shell_quote(value: &str) -> String {
+    format!("'{}'", value.replace('\'', r"'\''"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tmux::fake::Fake;
+    use crate::tmux::{Multiplexer, SessionOptions};
+
+    #[tokio::test]
+    async fn apply_creates_windows_and_panes() {
+        let layout: Layout = serde_yaml::from_str(
+            r#"
+windows:
+  - name: editor
+    panes:
+      - command: nvim
+  - name: server
+    cwd: api
+    layout: even-horizontal
+    panes:
+      - command: cargo watch
+      - cwd: ../web
+        split: horizontal
+        command: npm run dev
+"#,
+        )
+        .unwrap();
+        let mux = Fake::default();
+        let options = SessionOptions { layout: Some(layout), ..Default::default() };
+
+        mux.create_session("app", "/src/app", &options).await.unwrap();
+
+        assert_eq!(
+            mux.state().calls,
+            vec![
+                "new-session app /src/app",
+                "rename-window %0 editor",
+                "send-keys %0 nvim",
+                "new-window app server /src/app/api",
+                "send-keys %1 cargo watch",
+                "split-window %1 horizontal /src/app/api/../web",
+                "send-keys %2 npm run dev",
+                "select-layout %1 even-horizontal",
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn apply_moves_the_first_pane_to_its_directory() {
+        let layout = Layout {
+            windows: vec![Window {
+                cwd: Some("it's".into()),
+                panes: vec![Pane { command: Some("ls".into()), ..Default::default() }],
+                ..Default::default()
+            }],
+        };
+        let mux = Fake::default();
+
+        apply(&mux, "app", "/src/app", "%0", &layout).await.unwrap();
+
+        assert_eq!(
+            mux.state().calls,
+            vec![r"send-keys %0 cd '/src/app/it'\''s'", "send-keys %0 ls"]
+        );
+    }
+}
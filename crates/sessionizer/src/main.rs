@@ -1,12 +1,25 @@
 use clap::{Parser, Subcommand};
 
 mod config;
+mod daemon;
 mod directories;
+mod files;
 mod fzf;
+mod hooks;
+mod layout;
+mod naming;
+mod picker;
+mod project;
+mod prompt;
 mod sessions;
+mod snapshot;
+mod state;
+mod sync;
 mod tmux;
+mod worktree;
 
 use crate::config::Config;
+use crate::state::State;
 
 #[derive(Debug, Subcommand)]
 pub enum Commands {
@@ -19,6 +32,18 @@ pub enum Commands {
     /// Handle tmux sessions created through sessionizer
     #[clap(name = "sessions")]
     Sessions(crate::sessions::Cli),
+    /// Save and restore the layout of the running sessions
+    #[clap(name = "snapshot")]
+    Snapshot(crate::snapshot::Cli),
+    /// Jump to a running session, a previous session or a directory
+    #[clap(name = "open")]
+    Open,
+    /// Handle git worktrees opened as sessions
+    #[clap(name = "worktree")]
+    Worktree(crate::worktree::Cli),
+    /// Watch the directories in the background to list them instantly
+    #[clap(name = "daemon")]
+    Daemon,
 }
 
 #[derive(Debug, Parser)]
@@ -27,6 +52,9 @@ pub struct Cli {
     /// Custom path for the configuration file
     #[clap(short, long, env = "SESSIONIZER_CONFIG", global = true)]
     pub config: Option<String>,
+    /// Custom path for the state file
+    #[clap(long, env = "SESSIONIZER_STATE", global = true)]
+    pub state: Option<String>,
     #[command(subcommand)]
     pub command: Commands,
 }
@@ -76,10 +104,35 @@ async fn run() -> color_eyre::eyre::Result<()> {
     match cli.command {
         Commands::Config(cli) => crate::config::run(&config_path, cli).await,
         Commands::Directories(cli) => crate::directories::run(config, cli).await,
-        Commands::Sessions(cli) => crate::sessions::run(config, cli).await,
+        Commands::Sessions(sub) => {
+            let state = get_state(cli.state, &config)?;
+            crate::sessions::run(&crate::tmux::Tmux, config, state, sub).await
+        }
+        Commands::Snapshot(sub) => {
+            let state = get_state(cli.state, &config)?;
+            crate::snapshot::run(&crate::tmux::Tmux, config, state, sub).await
+        }
+        Commands::Open => {
+            let state = get_state(cli.state, &config)?;
+            crate::sessions::open(&crate::tmux::Tmux, &config, state).await
+        }
+        Commands::Worktree(sub) => {
+            let state = get_state(cli.state, &config)?;
+            crate::worktree::run(&crate::tmux::Tmux, config, state, sub).await
+        }
+        Commands::Daemon => crate::daemon::run(&config_path).await,
     }
 }
 
+fn get_state(path: Option<String>, config: &Config) -> color_eyre::eyre::Result<State> {
+    let state_path = match path {
+        Some(path) => path,
+        None => State::home()?,
+    };
+
+    State::load_or_migrate(&state_path, config)
+}
+
 fn get_config(cli: &Cli) -> color_eyre::eyre::Result<(Config, String)> {
     let config_path = match cli.config.clone() {
         Some(path) => path,
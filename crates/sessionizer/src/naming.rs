@@ -0,0 +1,184 @@
+//! Names of the tmux sessions created for directories.
+//!
+//! A directory gets its name the first time a session is created for it, and the name is stored
+//! in the state file so every command finds the same session afterwards, even if the naming
+//! configuration changes.
+
+use std::path::Path;
+
+use color_eyre::eyre::{bail, Error, Result};
+use serde::{Deserialize, Serialize};
+
+use crate::config::Config;
+
+/// How session names are derived from directories.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(try_from = "String", into = "String")]
+pub enum Naming {
+    /// The full path, e.g. `/home/me/src/api`.
+    #[default]
+    Path,
+    /// The directory name, e.g. `api`.
+    Basename,
+    /// The parent and directory names, e.g. `src/api`.
+    Parent,
+    /// A template replacing `{name}`, `{parent}` and `{path}`, e.g. `work-{name}`.
+    Template(String),
+}
+
+impl TryFrom<String> for Naming {
+    type Error = Error;
+
+    fn try_from(value: String) -> Result<Self> {
+        Ok(match value.as_str() {
+            "path" => Naming::Path,
+            "basename" => Naming::Basename,
+            "parent" => Naming::Parent,
+            _ if value.contains('{') => Naming::Template(value),
+            _ => bail!("unknown naming strategy: {}", value),
+        })
+    }
+}
+
+impl From<Naming> for String {
+    fn from(naming: Naming) -> Self {
+        match naming {
+            Naming::Path => "path".into(),
+            Naming::Basename => "basename".into(),
+            Naming::Parent => "parent".into(),
+            Naming::Template(template) => template,
+        }
+    }
+}
+
+impl Naming {
+    pub fn is_default(&self) -> bool {
+        *self == Naming::Path
+    }
+
+    /// Returns the name of the session rooted at `path`.
+    pub fn apply(&self, path: &str) -> String {
+        let file_name = |path: &Path| path.file_name().map(|n| n.to_string_lossy().to_string());
+        let name = file_name(Path::new(path)).unwrap_or_else(|| path.to_string());
+        let parent = Path::new(path).parent().and_then(file_name).unwrap_or_default();
+
+        match self {
+            Naming::Path => path.to_string(),
+            Naming::Basename => name,
+            Naming::Parent if parent.is_empty() => name,
+            Naming::Parent => format!("{}/{}", parent, name),
+            Naming::Template(template) => template
+                .replace("{name}", &name)
+                .replace("{parent}", &parent)
+                .replace("{path}", path),
+        }
+    }
+}
+
+/// Returns the name the session rooted at `path` should get, before disambiguation.
+///
+/// An `explicit` name, e.g. from the project configuration, comes first, followed by the
+/// `names` configured for the path, the `repo@branch` name of git worktrees and the `naming`
+/// strategy of the innermost directory containing the path, or the global one.
+pub fn preferred(config: &Config, path: &str, explicit: Option<&str>) -> String {
+    let configured = || {
+        config
+            .names
+            .iter()
+            .find(|(dir, _)| Path::new(shellexpand::tilde(dir).as_ref()) == Path::new(path))
+            .map(|(_, name)| name.clone())
+    };
+
+    explicit
+        .map(String::from)
+        .or_else(configured)
+        .or_else(|| crate::worktree::name(path))
+        .unwrap_or_else(|| {
+            let naming = config
+                .directories
+                .iter()
+                .filter(|d| d.naming.is_some() && d.contains(path))
+                .max_by_key(|d| d.path.len())
+                .and_then(|d| d.naming.as_ref())
+                .unwrap_or(&config.naming);
+            naming.apply(path)
+        })
+}
+
+/// Makes `name` unique for `path` by prefixing it with more of the parent directories, and
+/// numbering it as a last resort.
+pub fn disambiguate(name: &str, path: &str, taken: impl Fn(&str) -> bool) -> String {
+    if !taken(name) {
+        return name.to_string();
+    }
+
+    let parents: Vec<String> = Path::new(path)
+        .ancestors()
+        .skip(1)
+        .filter_map(|p| p.file_name())
+        .map(|n| n.to_string_lossy().to_string())
+        .collect();
+
+    let mut candidate = name.to_string();
+    for parent in parents {
+        candidate = format!("{}/{}", parent, candidate);
+        if !taken(&candidate) {
+            return candidate;
+        }
+    }
+
+    (2..).map(|i| format!("{}-{}", name, i)).find(|c| !taken(c)).unwrap()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::directories::Directory;
+
+    #[test]
+    fn strategies_derive_names_from_the_path() {
+        let path = "/home/me/src/api";
+
+        assert_eq!(Naming::Path.apply(path), "/home/me/src/api");
+        assert_eq!(Naming::Basename.apply(path), "api");
+        assert_eq!(Naming::Parent.apply(path), "src/api");
+        assert_eq!(Naming::Template("{parent}-{name}".into()).apply(path), "src-api");
+        assert_eq!(Naming::Parent.apply("/api"), "api");
+    }
+
+    #[test]
+    fn strategies_are_configured_with_strings() {
+        let naming: Naming = serde_yaml::from_str("basename").unwrap();
+        assert_eq!(naming, Naming::Basename);
+        let naming: Naming = serde_yaml::from_str("'{parent}/{name}'").unwrap();
+        assert_eq!(naming, Naming::Template("{parent}/{name}".into()));
+        assert!(serde_yaml::from_str::<Naming>("nope").is_err());
+    }
+
+    #[test]
+    fn names_come_from_the_most_specific_source() {
+        let mut config = Config::new("sessionizer.yaml");
+        config.naming = Naming::Basename;
+        config.directories = vec![Directory {
+            path: "/src/work".into(),
+            naming: Some(Naming::Parent),
+            ..Default::default()
+        }];
+        config.names = [("/src/dotfiles".to_string(), "dots".to_string())].into();
+
+        assert_eq!(preferred(&config, "/src/my.app", None), "my.app");
+        assert_eq!(preferred(&config, "/src/work/api", None), "work/api");
+        assert_eq!(preferred(&config, "/src/dotfiles", None), "dots");
+        assert_eq!(preferred(&config, "/src/dotfiles", Some("mine")), "mine");
+    }
+
+    #[test]
+    fn collisions_get_parent_directories_then_numbers() {
+        let taken = ["api", "work/api"];
+        let taken = |name: &str| taken.contains(&name);
+
+        assert_eq!(disambiguate("web", "/src/web", taken), "web");
+        assert_eq!(disambiguate("api", "/src/work/api", taken), "src/work/api");
+        assert_eq!(disambiguate("api", "/work/api", taken), "api-2");
+    }
+}
@@ -0,0 +1,138 @@
+mod fuzzy;
+mod tui;
+
+use color_eyre::eyre::{bail, eyre, Result, WrapErr};
+use serde::{Deserialize, Serialize};
+
+use crate::config::Config;
+use crate::state::State;
+
+/// Program used to pick sessions and directories.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Picker {
+    /// External `fzf` binary, falling back to the built-in picker if it isn't installed.
+    #[default]
+    Fzf,
+    /// External `sk` binary, falling back to the built-in picker if it isn't installed.
+    Skim,
+    /// Terminal picker shipped with sessionizer.
+    Builtin,
+}
+
+/// Shell command run when `key` is pressed.
+///
+/// `{}` is replaced by the current item and `{+}` by the selected ones.
+#[derive(Debug, Clone)]
+pub struct Binding {
+    /// fzf style key name, e.g. `ctrl-x`.
+    pub key: String,
+    pub command: String,
+    /// Command printing the new items once `command` is done.
+    pub reload: Option<String>,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct Request {
+    pub header: String,
+    pub items: Vec<String>,
+    pub multi: bool,
+    /// Shell command whose output is shown next to the list, with the same placeholders as
+    /// [`Binding::command`].
+    pub preview: Option<String>,
+    pub bindings: Vec<Binding>,
+    /// Keys accepting the selection like `Enter`, reported in [`Selection::key`].
+    pub expect: Vec<String>,
+}
+
+/// Items picked by the user.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Selection {
+    /// [`Request::expect`] key used to accept the items, `None` for `Enter`.
+    pub key: Option<String>,
+    pub items: Vec<String>,
+}
+
+impl Selection {
+    /// Returns the first picked item.
+    pub fn first(self) -> Result<String> {
+        self.items.into_iter().next().ok_or_else(|| eyre!("nothing selected"))
+    }
+}
+
+/// Key creating a git worktree of the picked repository.
+pub const NEW_WORKTREE: &str = "ctrl-t";
+
+/// Lets the user pick items with the configured picker.
+pub async fn pick(picker: Picker, request: Request) -> Result<Selection> {
+    let program = match picker {
+        Picker::Fzf => "fzf",
+        Picker::Skim => "sk",
+        Picker::Builtin => return builtin(request).await,
+    };
+
+    match crate::fzf::run(program, request.clone()).await {
+        Err(err)
+            if err
+                .downcast_ref::<std::io::Error>()
+                .is_some_and(|err| err.kind() == std::io::ErrorKind::NotFound) =>
+        {
+            log::debug!("{} is not installed, using the built-in picker", program);
+            builtin(request).await
+        }
+        result => result.wrap_err_with(|| format!("fail to run {}", program)),
+    }
+}
+
+async fn builtin(request: Request) -> Result<Selection> {
+    tokio::task::spawn_blocking(|| tui::run(request)).await?
+}
+
+/// Picks a session from the history, the most frecent first.
+pub async fn sessions(config: &Config, state: &State) -> Result<String> {
+    let request = Request {
+        header: "Press CTRL-X to delete a session, ALT-K to kill the selected sessions, TAB \
+            selecting several."
+            .into(),
+        items: state.ranked(state.history.iter().rev()),
+        multi: true,
+        preview: config.preview.clone(),
+        bindings: vec![
+            Binding {
+                key: "ctrl-x".into(),
+                command: "sessionizer sessions remove {}".into(),
+                reload: Some("sessionizer sessions history".into()),
+            },
+            Binding {
+                key: "alt-k".into(),
+                command: "sessionizer sessions kill {+}".into(),
+                reload: Some("sessionizer sessions history".into()),
+            },
+        ],
+        ..Default::default()
+    };
+
+    // Several sessions are only selected for the kill binding.
+    let selection = pick(config.picker, request).await?;
+    if selection.items.len() > 1 {
+        bail!("select a single session to go to, TAB only selects the sessions ALT-K kills");
+    }
+    selection.first()
+}
+
+/// Picks one of the configured directories, the most frecent first.
+///
+/// [`NEW_WORKTREE`] accepts a repository to create a worktree of.
+pub async fn directories(config: &Config, state: &State) -> Result<Selection> {
+    let request = Request {
+        header: "Select a directory from the list to start a new session, CTRL-T creates a \
+            worktree of the selected repository"
+            .into(),
+        items: state.ranked(&crate::directories::candidates(config).await?),
+        preview: config.preview.clone(),
+        expect: vec![NEW_WORKTREE.into()],
+        ..Default::default()
+    };
+
+    pick(config.picker, request).await
+}
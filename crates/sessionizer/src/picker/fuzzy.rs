@@ -0,0 +1,205 @@
+//! Fuzzy matching modeled after the fzf v1 algorithm.
+//!
+//! A pattern matches a text when its characters appear in order. The shortest window containing
+//! the match is scored, rewarding matches at word boundaries and consecutive runs, and
+//! penalizing the gaps between matched characters.
+
+const SCORE_MATCH: i64 = 16;
+const SCORE_GAP_START: i64 = -3;
+const SCORE_GAP_EXTENSION: i64 = -1;
+
+const BONUS_BOUNDARY: i64 = SCORE_MATCH / 2;
+const BONUS_NON_WORD: i64 = SCORE_MATCH / 2;
+const BONUS_BOUNDARY_DELIMITER: i64 = BONUS_BOUNDARY + 1;
+const BONUS_CAMEL: i64 = BONUS_BOUNDARY + SCORE_GAP_EXTENSION;
+const BONUS_CONSECUTIVE: i64 = -(SCORE_GAP_START + SCORE_GAP_EXTENSION);
+const BONUS_FIRST_CHAR_MULTIPLIER: i64 = 2;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Class {
+    NonWord,
+    Delimiter,
+    Lower,
+    Upper,
+    Number,
+}
+
+fn class(c: char) -> Class {
+    if c.is_lowercase() {
+        Class::Lower
+    } else if c.is_uppercase() {
+        Class::Upper
+    } else if c.is_numeric() {
+        Class::Number
+    } else if matches!(c, '/' | '-' | '_' | '.' | ':' | ',' | ';' | '|') {
+        Class::Delimiter
+    } else if c.is_alphabetic() {
+        Class::Lower
+    } else {
+        Class::NonWord
+    }
+}
+
+fn bonus(prev: Class, class: Class) -> i64 {
+    match (prev, class) {
+        (Class::NonWord, Class::Lower | Class::Upper | Class::Number) => BONUS_BOUNDARY,
+        (Class::Delimiter, Class::Lower | Class::Upper | Class::Number) => BONUS_BOUNDARY_DELIMITER,
+        (Class::Lower, Class::Upper) | (Class::Lower | Class::Upper, Class::Number) => BONUS_CAMEL,
+        (_, Class::NonWord | Class::Delimiter) => BONUS_NON_WORD,
+        _ => 0,
+    }
+}
+
+/// Result of matching a query against a text.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Match {
+    pub score: i64,
+    /// Indices of the matched characters in the text.
+    pub positions: Vec<usize>,
+}
+
+/// Scores `text` against a whitespace separated `query` where every term must match.
+///
+/// Matching is case insensitive unless the query contains an uppercase character.
+pub fn score(query: &str, text: &str) -> Option<Match> {
+    let case_sensitive = query.chars().any(char::is_uppercase);
+    let original: Vec<char> = text.chars().collect();
+    let text: Vec<char> = if case_sensitive {
+        original.clone()
+    } else {
+        original.iter().map(|c| c.to_lowercase().next().unwrap_or(*c)).collect()
+    };
+
+    let mut result = Match { score: 0, positions: vec![] };
+    for term in query.split_whitespace() {
+        let pattern: Vec<char> = term.chars().collect();
+        let matched = score_term(&pattern, &text, &original)?;
+        result.score += matched.score;
+        result.positions.extend(matched.positions);
+    }
+
+    result.positions.sort_unstable();
+    result.positions.dedup();
+
+    Some(result)
+}
+
+fn score_term(pattern: &[char], text: &[char], original: &[char]) -> Option<Match> {
+    // Find the end of the first occurrence of the pattern.
+    let mut index = 0;
+    let mut end = None;
+    for (i, c) in text.iter().enumerate() {
+        if *c == pattern[index] {
+            index += 1;
+            if index == pattern.len() {
+                end = Some(i);
+                break;
+            }
+        }
+    }
+    let end = end?;
+
+    // Walk back to find the shortest window ending there.
+    let mut index = pattern.len();
+    let mut start = end;
+    for i in (0..=end).rev() {
+        if text[i] == pattern[index - 1] {
+            index -= 1;
+            if index == 0 {
+                start = i;
+                break;
+            }
+        }
+    }
+
+    let mut score = 0;
+    let mut positions = Vec::with_capacity(pattern.len());
+    let mut index = 0;
+    let mut in_gap = false;
+    let mut consecutive = 0;
+    let mut first_bonus = 0;
+    let mut prev = if start > 0 { class(original[start - 1]) } else { Class::Delimiter };
+
+    for i in start..=end {
+        let current = class(original[i]);
+
+        if index < pattern.len() && text[i] == pattern[index] {
+            let mut bonus = bonus(prev, current);
+            if consecutive == 0 {
+                first_bonus = bonus;
+            } else {
+                if bonus >= BONUS_BOUNDARY && bonus > first_bonus {
+                    first_bonus = bonus;
+                }
+                bonus = bonus.max(first_bonus).max(BONUS_CONSECUTIVE);
+            }
+
+            score += SCORE_MATCH;
+            score += if index == 0 { bonus * BONUS_FIRST_CHAR_MULTIPLIER } else { bonus };
+            positions.push(i);
+            in_gap = false;
+            consecutive += 1;
+            index += 1;
+        } else {
+            score += if in_gap { SCORE_GAP_EXTENSION } else { SCORE_GAP_START };
+            in_gap = true;
+            consecutive = 0;
+            first_bonus = 0;
+        }
+
+        prev = current;
+    }
+
+    Some(Match { score, positions })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rank<'a>(query: &str, items: &[&'a str]) -> Vec<&'a str> {
+        let mut scored: Vec<_> =
+            items.iter().filter_map(|item| score(query, item).map(|m| (m.score, *item))).collect();
+        scored.sort_by_key(|(score, _)| std::cmp::Reverse(*score));
+        scored.into_iter().map(|(_, item)| item).collect()
+    }
+
+    #[test]
+    fn empty_queries_match_everything() {
+        assert_eq!(score("", "anything"), Some(Match { score: 0, positions: vec![] }));
+    }
+
+    #[test]
+    fn characters_must_appear_in_order() {
+        assert!(score("abc", "a_b_c").is_some());
+        assert!(score("acb", "a_b_c").is_none());
+        assert!(score("x", "a_b_c").is_none());
+    }
+
+    #[test]
+    fn every_term_must_match() {
+        assert!(score("src sess", "~/src/sessionizer").is_some());
+        assert!(score("src zzz", "~/src/sessionizer").is_none());
+    }
+
+    #[test]
+    fn matching_is_smart_case() {
+        assert!(score("sess", "~/src/Sessionizer").is_some());
+        assert!(score("Sess", "~/src/Sessionizer").is_some());
+        assert!(score("Sess", "~/src/sessionizer").is_none());
+    }
+
+    #[test]
+    fn positions_point_to_the_shortest_window() {
+        assert_eq!(score("ab", "a__ab").unwrap().positions, vec![3, 4]);
+    }
+
+    #[test]
+    fn boundaries_and_runs_rank_higher() {
+        assert_eq!(
+            rank("api", &["/src/rapid", "/src/work/api", "/src/a/p/i"]),
+            vec!["/src/work/api", "/src/a/p/i", "/src/rapid"]
+        );
+        assert_eq!(rank("fb", &["/src/fooBar", "/src/fxxb"]), vec!["/src/fooBar", "/src/fxxb"]);
+    }
+}
@@ -0,0 +1,505 @@
+//! Built-in terminal picker, drawn on stderr so it works without an external `fzf` binary.
+
+use std::collections::BTreeSet;
+use std::io::Write;
+
+use color_eyre::eyre::{bail, eyre, Result, WrapErr};
+use crossterm::event::{self, Event, KeyCode, KeyEvent, KeyEventKind, KeyModifiers};
+use crossterm::style::{Attribute, Color, Print, ResetColor, SetAttribute, SetForegroundColor};
+use crossterm::{cursor, queue, terminal};
+
+use super::fuzzy;
+use super::{Binding, Request, Selection};
+use crate::layout::shell_quote;
+
+/// Item matching the current query.
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Candidate {
+    index: usize,
+    score: i64,
+    positions: Vec<usize>,
+}
+
+/// Outcome of handling a key press.
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Action {
+    Continue,
+    Accept(Vec<String>),
+    Abort,
+}
+
+/// Filtering, cursor and selection state of the picker, independent of the terminal.
+#[derive(Debug)]
+struct Prompt {
+    items: Vec<String>,
+    query: String,
+    candidates: Vec<Candidate>,
+    cursor: usize,
+    offset: usize,
+    selected: BTreeSet<usize>,
+    multi: bool,
+}
+
+impl Prompt {
+    fn new(items: Vec<String>, multi: bool) -> Self {
+        let mut prompt = Self {
+            items,
+            query: String::new(),
+            candidates: vec![],
+            cursor: 0,
+            offset: 0,
+            selected: BTreeSet::new(),
+            multi,
+        };
+        prompt.filter();
+        prompt
+    }
+
+    /// Replaces the items, e.g. after a binding reloads them.
+    fn set_items(&mut self, items: Vec<String>) {
+        self.items = items;
+        self.selected.clear();
+        self.filter();
+    }
+
+    /// Recomputes the candidates for the current query, best matches first.
+    fn filter(&mut self) {
+        self.candidates = self
+            .items
+            .iter()
+            .enumerate()
+            .filter_map(|(index, item)| {
+                fuzzy::score(&self.query, item).map(|m| Candidate {
+                    index,
+                    score: m.score,
+                    positions: m.positions,
+                })
+            })
+            .collect();
+
+        if !self.query.trim().is_empty() {
+            let items = &self.items;
+            self.candidates.sort_by(|a, b| {
+                b.score
+                    .cmp(&a.score)
+                    .then(items[a.index].len().cmp(&items[b.index].len()))
+                    .then(a.index.cmp(&b.index))
+            });
+        }
+
+        self.cursor = 0;
+        self.offset = 0;
+    }
+
+    fn current(&self) -> Option<&str> {
+        self.candidates.get(self.cursor).map(|c| self.items[c.index].as_str())
+    }
+
+    /// Items an action applies to: the selected ones, or the one under the cursor.
+    fn targets(&self) -> Vec<String> {
+        if self.selected.is_empty() {
+            self.current().map(|s| vec![s.to_string()]).unwrap_or_default()
+        } else {
+            self.selected.iter().map(|i| self.items[*i].clone()).collect()
+        }
+    }
+
+    fn move_by(&mut self, delta: isize) {
+        if self.candidates.is_empty() {
+            return;
+        }
+        let last = self.candidates.len() - 1;
+        self.cursor = self.cursor.saturating_add_signed(delta).min(last);
+    }
+
+    /// Keeps the cursor inside a list of `height` visible rows.
+    fn scroll(&mut self, height: usize) {
+        if self.cursor < self.offset {
+            self.offset = self.cursor;
+        } else if height > 0 && self.cursor >= self.offset + height {
+            self.offset = self.cursor + 1 - height;
+        }
+    }
+
+    fn handle(&mut self, key: KeyEvent) -> Action {
+        let ctrl = key.modifiers.contains(KeyModifiers::CONTROL);
+
+        match key.code {
+            KeyCode::Esc => return Action::Abort,
+            KeyCode::Char('c' | 'g' | 'q') if ctrl => return Action::Abort,
+            KeyCode::Enter => {
+                let targets = self.targets();
+                if !targets.is_empty() {
+                    return Action::Accept(targets);
+                }
+            }
+            KeyCode::Up => self.move_by(-1),
+            KeyCode::Char('p' | 'k') if ctrl => self.move_by(-1),
+            KeyCode::Down => self.move_by(1),
+            KeyCode::Char('n' | 'j') if ctrl => self.move_by(1),
+            KeyCode::PageUp => self.move_by(-10),
+            KeyCode::PageDown => self.move_by(10),
+            KeyCode::Tab | KeyCode::BackTab if self.multi => {
+                if let Some(candidate) = self.candidates.get(self.cursor) {
+                    if !self.selected.remove(&candidate.index) {
+                        self.selected.insert(candidate.index);
+                    }
+                }
+                self.move_by(if key.code == KeyCode::Tab { 1 } else { -1 });
+            }
+            KeyCode::Backspace if self.query.pop().is_some() => self.filter(),
+            KeyCode::Char('u') if ctrl => {
+                self.query.clear();
+                self.filter();
+            }
+            KeyCode::Char('w') if ctrl => {
+                let trimmed = self.query.trim_end();
+                let keep = trimmed.rfind(' ').map(|i| i + 1).unwrap_or(0);
+                self.query.truncate(keep);
+                self.filter();
+            }
+            KeyCode::Char(c) if !ctrl && !key.modifiers.contains(KeyModifiers::ALT) => {
+                self.query.push(c);
+                self.filter();
+            }
+            _ => {}
+        }
+
+        Action::Continue
+    }
+}
+
+/// Parses fzf style key names such as `ctrl-x` or `alt-d`.
+fn parse_key(name: &str) -> Result<(KeyCode, KeyModifiers)> {
+    let (modifiers, key) = match name.split_once('-') {
+        Some(("ctrl", key)) => (KeyModifiers::CONTROL, key),
+        Some(("alt", key)) => (KeyModifiers::ALT, key),
+        _ => (KeyModifiers::NONE, name),
+    };
+
+    let mut chars = key.chars();
+    match (chars.next(), chars.next()) {
+        (Some(c), None) => Ok((KeyCode::Char(c), modifiers)),
+        _ => Err(eyre!("unsupported key binding: {}", name)),
+    }
+}
+
+/// Replaces the fzf style `{}` (current item) and `{+}` (selected items) placeholders.
+fn expand(command: &str, prompt: &Prompt) -> String {
+    let targets = prompt.targets().iter().map(|t| shell_quote(t)).collect::<Vec<_>>().join(" ");
+    let current = prompt.current().map(shell_quote).unwrap_or_default();
+
+    command.replace("{+}", &targets).replace("{}", &current)
+}
+
+fn shell(command: &str) -> std::process::Command {
+    let mut cmd = std::process::Command::new("sh");
+    cmd.args(["-c", command]).stdin(std::process::Stdio::null());
+    cmd
+}
+
+/// Runs a binding and returns the reloaded items, if it reloads them.
+fn execute(binding: &Binding, prompt: &Prompt) -> Result<Option<Vec<String>>> {
+    let command = expand(&binding.command, prompt);
+    log::debug!("$ sh -c {:?}", command);
+    shell(&command)
+        .stdout(std::process::Stdio::null())
+        .stderr(std::process::Stdio::null())
+        .status()
+        .wrap_err("fail to run binding")?;
+
+    let Some(reload) = &binding.reload else {
+        return Ok(None);
+    };
+
+    let output = shell(reload).output().wrap_err("fail to reload the items")?;
+    let items = String::from_utf8(output.stdout)?;
+
+    Ok(Some(items.lines().filter(|l| !l.is_empty()).map(String::from).collect()))
+}
+
+fn preview(command: &str, prompt: &Prompt) -> Vec<String> {
+    if prompt.current().is_none() {
+        return vec![];
+    }
+
+    match shell(&expand(command, prompt)).output() {
+        Ok(output) => {
+            let mut text = String::from_utf8_lossy(&output.stdout).to_string();
+            text.push_str(&String::from_utf8_lossy(&output.stderr));
+            text.lines().map(|l| l.replace('\t', "    ")).collect()
+        }
+        Err(err) => vec![format!("fail to run preview: {}", err)],
+    }
+}
+
+/// Restores the terminal when dropped, even if drawing fails half way.
+struct Screen;
+
+impl Screen {
+    fn enter() -> Result<Self> {
+        terminal::enable_raw_mode().wrap_err("fail to enable raw mode")?;
+        let screen = Self;
+        crossterm::execute!(std::io::stderr(), terminal::EnterAlternateScreen)?;
+        Ok(screen)
+    }
+}
+
+impl Drop for Screen {
+    fn drop(&mut self) {
+        let _ =
+            crossterm::execute!(std::io::stderr(), terminal::LeaveAlternateScreen, cursor::Show);
+        let _ = terminal::disable_raw_mode();
+    }
+}
+
+fn truncate(text: &str, width: usize) -> String {
+    text.chars().take(width).collect()
+}
+
+fn draw(
+    out: &mut impl Write,
+    prompt: &mut Prompt,
+    header: &str,
+    preview: Option<&[String]>,
+) -> Result<()> {
+    let (width, height) = terminal::size()?;
+    let (width, height) = (width as usize, height as usize);
+    let list_width = if preview.is_some() { width / 2 } else { width };
+    let list_height = height.saturating_sub(2);
+    prompt.scroll(list_height);
+
+    queue!(out, cursor::Hide, terminal::Clear(terminal::ClearType::All), cursor::MoveTo(0, 0))?;
+    queue!(out, SetForegroundColor(Color::Blue), Print("> "), ResetColor)?;
+    queue!(out, Print(truncate(&prompt.query, width.saturating_sub(2))))?;
+
+    let info = format!("  {}/{}", prompt.candidates.len(), prompt.items.len());
+    let info = if prompt.selected.is_empty() {
+        info
+    } else {
+        format!("{} ({})", info, prompt.selected.len())
+    };
+    queue!(out, cursor::MoveTo(0, 1), SetForegroundColor(Color::DarkGrey))?;
+    queue!(out, Print(truncate(&format!("{} {}", info, header), list_width)), ResetColor)?;
+
+    let rows = prompt.candidates.iter().enumerate().skip(prompt.offset).take(list_height);
+    for (row, (position, candidate)) in rows.enumerate() {
+        let is_current = position == prompt.cursor;
+        queue!(out, cursor::MoveTo(0, (row + 2) as u16))?;
+
+        let marker = if is_current { ">" } else { " " };
+        let selected = if prompt.selected.contains(&candidate.index) { "*" } else { " " };
+        queue!(out, SetForegroundColor(Color::Red), Print(marker), Print(selected), ResetColor)?;
+        if is_current {
+            queue!(out, SetAttribute(Attribute::Bold))?;
+        }
+
+        let text = &prompt.items[candidate.index];
+        for (i, c) in text.chars().take(list_width.saturating_sub(2)).enumerate() {
+            if candidate.positions.binary_search(&i).is_ok() {
+                queue!(out, SetForegroundColor(Color::Green), Print(c), ResetColor)?;
+                if is_current {
+                    queue!(out, SetAttribute(Attribute::Bold))?;
+                }
+            } else {
+                queue!(out, Print(c))?;
+            }
+        }
+        queue!(out, SetAttribute(Attribute::Reset))?;
+    }
+
+    if let Some(lines) = preview {
+        let column = list_width as u16;
+        for row in 0..height {
+            queue!(out, cursor::MoveTo(column, row as u16))?;
+            queue!(out, SetForegroundColor(Color::DarkGrey), Print("│"), ResetColor)?;
+            if let Some(line) = lines.get(row) {
+                queue!(
+                    out,
+                    Print(" "),
+                    Print(truncate(line, width.saturating_sub(list_width + 2)))
+                )?;
+            }
+        }
+    }
+
+    let column = 2 + prompt.query.chars().count().min(width.saturating_sub(3));
+    queue!(out, cursor::MoveTo(column as u16, 0), cursor::Show)?;
+    out.flush()?;
+
+    Ok(())
+}
+
+/// Runs the picker until the user accepts or aborts the selection.
+pub(super) fn run(request: Request) -> Result<Selection> {
+    let bindings = request
+        .bindings
+        .iter()
+        .map(|binding| parse_key(&binding.key).map(|key| (key, binding)))
+        .collect::<Result<Vec<_>>>()?;
+    let expect = request
+        .expect
+        .iter()
+        .map(|name| parse_key(name).map(|key| (key, name)))
+        .collect::<Result<Vec<_>>>()?;
+
+    let mut prompt = Prompt::new(request.items, request.multi);
+    let _screen = Screen::enter()?;
+    let mut out = std::io::stderr();
+    let mut previewed: Option<(Option<String>, Vec<String>)> = None;
+
+    loop {
+        let lines = request.preview.as_deref().map(|command| {
+            let current = prompt.current().map(String::from);
+            match &previewed {
+                Some((item, lines)) if *item == current => lines.clone(),
+                _ => {
+                    let lines = preview(command, &prompt);
+                    previewed = Some((current, lines.clone()));
+                    lines
+                }
+            }
+        });
+        draw(&mut out, &mut prompt, &request.header, lines.as_deref())?;
+
+        let key = match event::read()? {
+            Event::Key(key) if key.kind != KeyEventKind::Release => key,
+            _ => continue,
+        };
+
+        if let Some((_, name)) = expect.iter().find(|(k, _)| *k == (key.code, key.modifiers)) {
+            let items = prompt.targets();
+            if !items.is_empty() {
+                return Ok(Selection { key: Some(name.to_string()), items });
+            }
+            continue;
+        }
+
+        if let Some((_, binding)) = bindings.iter().find(|(k, _)| *k == (key.code, key.modifiers)) {
+            if let Some(items) = execute(binding, &prompt)? {
+                prompt.set_items(items);
+                previewed = None;
+            }
+            continue;
+        }
+
+        match prompt.handle(key) {
+            Action::Continue => {}
+            Action::Accept(items) => return Ok(Selection { key: None, items }),
+            Action::Abort => bail!("picker aborted"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn key(code: KeyCode) -> KeyEvent {
+        KeyEvent::new(code, KeyModifiers::NONE)
+    }
+
+    fn ctrl(c: char) -> KeyEvent {
+        KeyEvent::new(KeyCode::Char(c), KeyModifiers::CONTROL)
+    }
+
+    fn prompt(items: &[&str], multi: bool) -> Prompt {
+        Prompt::new(items.iter().map(|s| s.to_string()).collect(), multi)
+    }
+
+    fn typed(prompt: &mut Prompt, text: &str) {
+        for c in text.chars() {
+            assert_eq!(prompt.handle(key(KeyCode::Char(c))), Action::Continue);
+        }
+    }
+
+    #[test]
+    fn typing_filters_and_ranks_the_items() {
+        let mut prompt = prompt(&["/src/rapid", "/src/work/api", "/src/web"], false);
+        assert_eq!(prompt.candidates.len(), 3);
+
+        typed(&mut prompt, "api");
+        assert_eq!(prompt.current(), Some("/src/work/api"));
+        assert_eq!(prompt.candidates.len(), 2);
+
+        prompt.handle(key(KeyCode::Backspace));
+        prompt.handle(ctrl('u'));
+        assert_eq!(prompt.query, "");
+        assert_eq!(prompt.current(), Some("/src/rapid"));
+    }
+
+    #[test]
+    fn enter_accepts_the_current_item() {
+        let mut prompt = prompt(&["a", "b", "c"], false);
+
+        prompt.handle(key(KeyCode::Down));
+        prompt.handle(ctrl('n'));
+        prompt.handle(ctrl('n'));
+        prompt.handle(ctrl('p'));
+
+        assert_eq!(prompt.handle(key(KeyCode::Enter)), Action::Accept(vec!["b".into()]));
+    }
+
+    #[test]
+    fn tab_selects_multiple_items() {
+        let mut prompt = prompt(&["a", "b", "c"], true);
+
+        prompt.handle(key(KeyCode::Tab));
+        prompt.handle(key(KeyCode::Down));
+        prompt.handle(key(KeyCode::Tab));
+
+        assert_eq!(
+            prompt.handle(key(KeyCode::Enter)),
+            Action::Accept(vec!["a".into(), "c".into()])
+        );
+    }
+
+    #[test]
+    fn tab_is_ignored_without_multi_select() {
+        let mut prompt = prompt(&["a", "b"], false);
+
+        prompt.handle(key(KeyCode::Tab));
+
+        assert!(prompt.selected.is_empty());
+        assert_eq!(prompt.handle(key(KeyCode::Enter)), Action::Accept(vec!["a".into()]));
+    }
+
+    #[test]
+    fn escape_aborts_and_enter_needs_a_match() {
+        let mut prompt = prompt(&["a"], false);
+        typed(&mut prompt, "zz");
+
+        assert_eq!(prompt.handle(key(KeyCode::Enter)), Action::Continue);
+        assert_eq!(prompt.handle(key(KeyCode::Esc)), Action::Abort);
+    }
+
+    #[test]
+    fn scroll_keeps_the_cursor_visible() {
+        let mut prompt = prompt(&["a", "b", "c", "d", "e"], false);
+
+        prompt.move_by(4);
+        prompt.scroll(2);
+        assert_eq!(prompt.offset, 3);
+
+        prompt.move_by(-4);
+        prompt.scroll(2);
+        assert_eq!(prompt.offset, 0);
+    }
+
+    #[test]
+    fn placeholders_expand_to_quoted_items() {
+        let mut prompt = prompt(&["it's", "b"], true);
+        assert_eq!(expand("rm {}", &prompt), r"rm 'it'\''s'");
+
+        prompt.handle(key(KeyCode::Tab));
+        prompt.handle(key(KeyCode::Tab));
+        assert_eq!(expand("rm {+}", &prompt), r"rm 'it'\''s' 'b'");
+    }
+
+    #[test]
+    fn key_names_follow_fzf() {
+        assert_eq!(parse_key("ctrl-x").unwrap(), (KeyCode::Char('x'), KeyModifiers::CONTROL));
+        assert_eq!(parse_key("alt-d").unwrap(), (KeyCode::Char('d'), KeyModifiers::ALT));
+        assert!(parse_key("ctrl-space").is_err());
+    }
+}
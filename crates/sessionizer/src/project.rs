@@ -0,0 +1,272 @@
+use std::path::{Path, PathBuf};
+
+use color_eyre::eyre::{eyre, Result, WrapErr};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+use crate::config::Config;
+use crate::hooks::Hooks;
+use crate::layout::Layout;
+
+/// File names of the project-local configuration, in lookup order.
+pub const FILES: [&str; 2] = [".sessionizer.yaml", ".sessionizer.toml"];
+
+/// Project-local configuration merged over the global [`Config`] when creating a session.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct Project {
+    /// Name of the tmux session, used instead of the one derived from the directory.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
+    /// `KEY=value` entries that override the global and directory `env`.
+    #[serde(default)]
+    pub env: Vec<String>,
+    /// Layout that replaces the one of the tracked directory.
+    #[serde(default)]
+    pub layout: Option<Layout>,
+    /// Lifecycle hooks run after the global and directory ones.
+    #[serde(default)]
+    pub hooks: Hooks,
+}
+
+impl Project {
+    /// Returns the path of the project configuration file inside `dir`, if there's one.
+    ///
+    /// The global configuration file at `config` is never taken for a project one, as happens
+    /// with the default `~/.sessionizer.yaml` for sessions rooted at `$HOME`.
+    pub fn find(dir: &str, config: &str) -> Option<PathBuf> {
+        let config = std::fs::canonicalize(config).unwrap_or_else(|_| PathBuf::from(config));
+
+        FILES
+            .iter()
+            .map(|file| Path::new(dir).join(file))
+            .filter(|path| path.is_file())
+            .find(|path| std::fs::canonicalize(path).map_or(true, |path| path != config))
+    }
+
+    /// Parses a project configuration file as YAML or TOML depending on its extension.
+    pub fn parse(path: &Path, text: &str) -> Result<Self> {
+        match path.extension().and_then(|e| e.to_str()) {
+            Some("toml") => toml::from_str(text).wrap_err("fail to deserialize project config"),
+            _ => serde_yaml::from_str(text).wrap_err("fail to deserialize project config"),
+        }
+    }
+
+    /// Loads the project configuration of `dir`.
+    ///
+    /// Files whose hash isn't in the trust allowlist are ignored with a warning, so a freshly
+    /// cloned repository can't run commands through its layout or hooks.
+    pub fn load(config: &Config, dir: &str) -> Result<Option<Self>> {
+        let Ok(dir) = std::fs::canonicalize(dir) else {
+            return Ok(None);
+        };
+        let Some(path) = Self::find(&dir.to_string_lossy(), config.path()) else {
+            return Ok(None);
+        };
+
+        let text = std::fs::read_to_string(&path)
+            .wrap_err_with(|| format!("fail to read {}", path.display()))?;
+
+        if !Trust::load(config.path())?.is_trusted(&path, &hash(&text)) {
+            eprintln!(
+                "Ignoring untrusted project config {}. Run `sessionizer config trust {}` to use it.",
+                path.display(),
+                dir.display()
+            );
+            return Ok(None);
+        }
+
+        log::debug!("Loading project config from {}", path.display());
+        Self::parse(&path, &text).map(Some)
+    }
+}
+
+/// Returns the hex encoded SHA-256 of a project configuration file contents.
+pub fn hash(text: &str) -> String {
+    format!("{:x}", Sha256::digest(text.as_bytes()))
+}
+
+/// Allowlist of trusted project configuration files.
+///
+/// It lives next to the global configuration file and uses the `sha256sum` format: one
+/// `<hash>  <path>` line per trusted file.
+#[derive(Debug, Default)]
+pub struct Trust {
+    path: PathBuf,
+    /// Global configuration file the allowlist belongs to.
+    config: String,
+    entries: Vec<(String, PathBuf)>,
+}
+
+impl Trust {
+    /// Loads the allowlist that belongs to the configuration file at `config`.
+    pub fn load(config: &str) -> Result<Self> {
+        let path = Path::new(config).with_extension("trust");
+
+        if !path.exists() {
+            return Ok(Self { path, config: config.to_string(), entries: vec![] });
+        }
+
+        let entries = std::fs::read_to_string(&path)
+            .wrap_err("fail to read the trust file")?
+            .lines()
+            .filter_map(|line| line.split_once("  "))
+            .map(|(hash, file)| (hash.to_string(), PathBuf::from(file)))
+            .collect();
+
+        Ok(Self { path, config: config.to_string(), entries })
+    }
+
+    pub fn save(&self) -> Result<()> {
+        let text: String = self
+            .entries
+            .iter()
+            .map(|(hash, file)| format!("{}  {}\n", hash, file.display()))
+            .collect();
+
+        crate::files::write_atomic(&self.path.to_string_lossy(), text.as_bytes())
+            .wrap_err("fail to save the trust file")
+    }
+
+    /// Applies `f` to the latest allowlist on disk and saves the result while holding the
+    /// allowlist lock.
+    pub fn update<T>(&mut self, f: impl FnOnce(&mut Self) -> Result<T>) -> Result<T> {
+        let _lock = crate::files::Lock::acquire(&self.path.to_string_lossy())?;
+
+        *self = Self::load(&self.config)?;
+        let result = f(self)?;
+        self.save()?;
+
+        Ok(result)
+    }
+
+    /// Checks if `file` is trusted with the given contents `hash`.
+    pub fn is_trusted(&self, file: &Path, hash: &str) -> bool {
+        self.entries.iter().any(|(h, f)| h == hash && f == file)
+    }
+
+    /// Trusts the current contents of the project configuration in `dir`.
+    pub fn allow(&mut self, dir: &str) -> Result<PathBuf> {
+        let file = self.file(dir)?;
+        let text = std::fs::read_to_string(&file)
+            .wrap_err_with(|| format!("fail to read {}", file.display()))?;
+        // Make sure we never trust a file we can't use.
+        Project::parse(&file, &text)?;
+
+        self.entries.retain(|(_, f)| f != &file);
+        self.entries.push((hash(&text), file.clone()));
+
+        Ok(file)
+    }
+
+    /// Stops trusting the project configuration in `dir`.
+    pub fn revoke(&mut self, dir: &str) -> Result<PathBuf> {
+        let file = self.file(dir)?;
+        self.entries.retain(|(_, f)| f != &file);
+
+        Ok(file)
+    }
+
+    fn file(&self, dir: &str) -> Result<PathBuf> {
+        let dir =
+            std::fs::canonicalize(dir).wrap_err_with(|| format!("fail to resolve {}", dir))?;
+
+        Project::find(&dir.to_string_lossy(), &self.config)
+            .ok_or_else(|| eyre!("no project config found in {}", dir.display()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn setup(file: &str, text: &str) -> (tempfile::TempDir, Config, String) {
+        let dir = tempfile::tempdir().unwrap();
+        let config = Config::new(&dir.path().join("sessionizer.yaml").to_string_lossy());
+        let project = std::fs::canonicalize(dir.path()).unwrap().join("project");
+        std::fs::create_dir(&project).unwrap();
+        std::fs::write(project.join(file), text).unwrap();
+        (dir, config, project.to_string_lossy().to_string())
+    }
+
+    #[test]
+    fn untrusted_projects_are_ignored() {
+        let (_dir, config, project) = setup(".sessionizer.yaml", "env: [A=1]");
+
+        assert_eq!(Project::load(&config, &project).unwrap(), None);
+    }
+
+    #[test]
+    fn trusted_projects_are_loaded_until_they_change() {
+        let (_dir, config, project) = setup(".sessionizer.toml", "env = [\"A=1\"]");
+
+        let mut trust = Trust::load(config.path()).unwrap();
+        trust.allow(&project).unwrap();
+        trust.save().unwrap();
+
+        let loaded = Project::load(&config, &project).unwrap().unwrap();
+        assert_eq!(loaded.env, vec!["A=1"]);
+
+        std::fs::write(Path::new(&project).join(".sessionizer.toml"), "env = [\"A=2\"]").unwrap();
+        assert_eq!(Project::load(&config, &project).unwrap(), None);
+    }
+
+    #[test]
+    fn revoked_projects_are_ignored() {
+        let (_dir, config, project) = setup(".sessionizer.yaml", "env: [A=1]");
+
+        let mut trust = Trust::load(config.path()).unwrap();
+        trust.allow(&project).unwrap();
+        trust.revoke(&project).unwrap();
+        trust.save().unwrap();
+
+        assert_eq!(Project::load(&config, &project).unwrap(), None);
+    }
+
+    #[test]
+    fn the_global_config_is_not_a_project_config() {
+        let dir = tempfile::tempdir().unwrap();
+        let home = std::fs::canonicalize(dir.path()).unwrap();
+        let config = Config::new(&home.join(".sessionizer.yaml").to_string_lossy());
+        config.save().unwrap();
+        let home = home.to_string_lossy();
+
+        assert_eq!(Project::find(&home, config.path()), None);
+        assert_eq!(Project::load(&config, &home).unwrap(), None);
+        assert!(Trust::load(config.path()).unwrap().allow(&home).is_err());
+    }
+
+    #[test]
+    fn concurrent_updates_keep_every_trusted_file() {
+        let dir = tempfile::tempdir().unwrap();
+        let config = Config::new(&dir.path().join("sessionizer.yaml").to_string_lossy());
+        let projects: Vec<String> = (0..8)
+            .map(|i| {
+                let project = std::fs::canonicalize(dir.path()).unwrap().join(i.to_string());
+                std::fs::create_dir(&project).unwrap();
+                std::fs::write(project.join(".sessionizer.yaml"), "env: [A=1]").unwrap();
+                project.to_string_lossy().to_string()
+            })
+            .collect();
+
+        std::thread::scope(|scope| {
+            for project in &projects {
+                let config = config.path();
+                scope.spawn(move || {
+                    let mut trust = Trust::load(config).unwrap();
+                    trust.update(|trust| trust.allow(project)).unwrap();
+                });
+            }
+        });
+
+        for project in &projects {
+            assert!(Project::load(&config, project).unwrap().is_some());
+        }
+    }
+
+    #[test]
+    fn invalid_projects_cannot_be_trusted() {
+        let (_dir, config, project) = setup(".sessionizer.yaml", "env: {");
+
+        assert!(Trust::load(config.path()).unwrap().allow(&project).is_err());
+    }
+}
@@ -0,0 +1,30 @@
+use std::io::{BufRead, IsTerminal, Write};
+
+use color_eyre::eyre::{bail, Result, WrapErr};
+
+/// Checks if the user can answer questions, which isn't the case when run from a picker binding.
+pub fn is_interactive() -> bool {
+    std::io::stdin().is_terminal()
+}
+
+/// Prints `question` on stderr and returns the trimmed line read from stdin.
+pub fn ask(question: &str) -> Result<String> {
+    eprint!("{} ", question);
+    std::io::stderr().flush()?;
+
+    let mut answer = String::new();
+    let read =
+        std::io::stdin().lock().read_line(&mut answer).wrap_err("fail to read the answer")?;
+    if read == 0 {
+        bail!("no answer to: {}", question);
+    }
+
+    Ok(answer.trim().to_string())
+}
+
+/// Asks a yes or no `question`, defaulting to no.
+pub fn confirm(question: &str) -> Result<bool> {
+    let answer = ask(&format!("{} [y/N]", question))?;
+
+    Ok(matches!(answer.to_lowercase().as_str(), "y" | "yes"))
+}
@@ -1,23 +1,30 @@
-use clap::{Parser, Subcommand};
+use clap::{Parser, Subcommand, ValueEnum};
 use color_eyre::eyre::bail;
-use color_eyre::eyre::{ContextCompat, Result};
-use serde::{Deserialize, Serialize};
+use color_eyre::eyre::{ContextCompat, Result, WrapErr};
+use serde::Serialize;
 
 use crate::config::Config;
-use crate::fzf;
-use crate::tmux;
-
-#[derive(Debug, Serialize, Deserialize)]
-pub struct Sessions {
-    pub current: String,
-    pub history: Vec<String>,
-}
+use crate::hooks::{self, Context, Event, Hooks};
+use crate::layout::Layout;
+use crate::naming;
+use crate::picker;
+use crate::project::Project;
+use crate::state::State;
+use crate::sync::{self, Action, Step};
+use crate::tmux::{Multiplexer, SessionOptions, TmuxSession};
 
 #[derive(Debug, Subcommand)]
 pub enum Commands {
     /// List the previously visited sessions.
     #[clap(name = "history", alias = "list")]
     History,
+    /// List the sessions with their tmux and git status.
+    #[clap(name = "ls")]
+    Ls {
+        /// Output format.
+        #[clap(short, long, value_enum, default_value_t)]
+        format: Format,
+    },
     /// Go to a session.
     #[clap(name = "go")]
     Go {
@@ -39,6 +46,19 @@ pub enum Commands {
         #[clap(short, long)]
         set: bool,
     },
+    /// Remove the history entries whose directory is gone, or beyond the history limits.
+    #[clap(name = "prune")]
+    Prune {
+        /// Print the entries that would be removed without removing them
+        #[clap(short = 'n', long)]
+        dry_run: bool,
+    },
+    /// Kill tmux sessions and remove them from the history.
+    #[clap(name = "kill", alias = "k")]
+    Kill {
+        /// Directories or names of the sessions, picked when none is given
+        sessions: Vec<String>,
+    },
     /// Remove a running session.
     #[clap(name = "remove", alias = "rm")]
     Remove {
@@ -66,6 +86,12 @@ pub enum Commands {
         /// Reverse the action to synchronize the sessions from `tmux`
         #[clap(short, long)]
         reverse: bool,
+        /// Print what would be created, killed or kept without doing it
+        #[clap(short = 'n', long)]
+        dry_run: bool,
+        /// Apply the changes without asking for confirmation
+        #[clap(short, long)]
+        yes: bool,
     },
 }
 
@@ -77,33 +103,614 @@ pub struct Cli {
     pub command: Commands,
 }
 
-pub async fn run(config: Config, cli: Cli) -> Result<()> {
+pub async fn run(mux: &impl Multiplexer, config: Config, state: State, cli: Cli) -> Result<()> {
     match cli.command {
-        Commands::History => history(config).await,
-        Commands::Go { session } => go(config, session).await,
-        Commands::Add { session, set } => add(config, session, set).await,
-        Commands::Remove { session } => remove(config, session).await,
-        Commands::Next { show } => next(config, show).await,
-        Commands::Previous { show } => previous(config, show).await,
-        Commands::Sync { reverse } => sync(config, reverse).await,
-        Commands::New { session } => new(config, session).await,
+        Commands::History => history(state).await,
+        Commands::Ls { format } => ls(mux, &config, state, format).await,
+        Commands::Go { session } => go(mux, &config, state, session).await,
+        Commands::Add { session, set } => add(mux, &config, state, session, set).await,
+        Commands::Remove { session } => remove(mux, &config, state, session).await,
+        Commands::Kill { sessions } => kill(mux, &config, state, sessions).await,
+        Commands::Prune { dry_run } => prune(&config, state, dry_run).await,
+        Commands::Next { show } => next(mux, &config, state, show).await,
+        Commands::Previous { show } => previous(mux, &config, state, show).await,
+        Commands::Sync { reverse, dry_run, yes } => {
+            sync(mux, &config, state, reverse, dry_run, yes).await
+        }
+        Commands::New { session } => new(mux, &config, state, session).await,
+    }
+}
+
+/// Settings of the tmux session rooted at a directory.
+pub struct Setup {
+    /// Directory the session is rooted at.
+    path: String,
+    /// `env` entries of the project configuration, expanded by [`Setup::options`].
+    env: Vec<String>,
+    layout: Option<Layout>,
+    pub hooks: Hooks,
+    /// Session name set by the project configuration.
+    pub name: Option<String>,
+}
+
+impl Setup {
+    /// Builds the options of the session, expanding its environment only when it's created.
+    pub fn options(&self, config: &Config) -> SessionOptions {
+        SessionOptions { env: config.env(&self.path, &self.env), layout: self.layout.clone() }
+    }
+}
+
+/// Builds the settings and hooks of the tmux session rooted at `session`.
+///
+/// The trusted project configuration found in `session`, if any, is merged over the global one.
+pub fn resolve(config: &Config, session: &str) -> Result<Setup> {
+    let project = Project::load(config, session)?.unwrap_or_default();
+
+    Ok(Setup {
+        path: session.to_string(),
+        env: project.env,
+        layout: project.layout.or_else(|| config.layout(session).cloned()),
+        hooks: config.hooks(session, &project.hooks),
+        name: project.name,
+    })
+}
+
+/// Returns the tmux session name of `session`, without assigning one if it has none yet.
+pub fn name_of(config: &Config, state: &State, session: &str) -> String {
+    state.names.get(session).cloned().unwrap_or_else(|| naming::preferred(config, session, None))
+}
+
+/// Returns the directory of the session called `name`, the reverse of [`name_of`].
+pub fn path_of(config: &Config, state: &State, name: &str) -> Option<String> {
+    state
+        .path_of(name)
+        .map(String::from)
+        .or_else(|| state.history.iter().find(|path| name_of(config, state, path) == name).cloned())
+}
+
+/// Returns the tmux session name of `session`, assigning and saving one if it has none yet.
+///
+/// New names never match a running session of another directory, which would be attached
+/// instead of creating the session.
+async fn assign(
+    mux: &impl Multiplexer,
+    config: &Config,
+    state: &mut State,
+    session: &str,
+    explicit: Option<&str>,
+) -> Result<String> {
+    if let Some(name) = state.names.get(session) {
+        return Ok(name.clone());
+    }
+
+    let running: Vec<String> = if mux.is_active().await? { mux.sessions().await? } else { vec![] }
+        .into_iter()
+        .filter(|running| running.source.as_deref() != Some(session))
+        .map(|running| running.name)
+        .collect();
+    let preferred = naming::preferred(config, session, explicit);
+    state.update(|state| state.assign(session, &preferred, &running))
+}
+
+/// Creates the session rooted at `session`, unless it's already running, and runs its
+/// `on_create` hooks.
+///
+/// Returns the name of the session.
+async fn create(
+    mux: &impl Multiplexer,
+    config: &Config,
+    state: &mut State,
+    session: &str,
+) -> Result<String> {
+    let setup = resolve(config, session)?;
+    let name = assign(mux, config, state, session, setup.name.as_deref()).await?;
+
+    if !mux.has_session(&name).await? {
+        mux.create_session(&name, session, &setup.options(config)).await?;
+
+        let context = Context { session: &name, path: session, previous: None };
+        hooks::run(&setup.hooks, Event::Create, context).await;
+    }
+
+    Ok(name)
+}
+
+/// Moves the client to `session`, creating it if needed, and runs the lifecycle hooks.
+///
+/// `previous` is the session the client is leaving, if any.
+async fn switch(
+    mux: &impl Multiplexer,
+    config: &Config,
+    state: &mut State,
+    session: &str,
+    previous: Option<&str>,
+) -> Result<()> {
+    let setup = resolve(config, session)?;
+    let previous = previous.filter(|p| *p != session);
+
+    if let Some(previous) = previous {
+        let leaving = resolve(config, previous)?.hooks;
+        let name = name_of(config, state, previous);
+        let context = Context { session: &name, path: previous, previous: None };
+        hooks::run(&leaving, Event::Leave, context).await;
+    }
+
+    let name = assign(mux, config, state, session, setup.name.as_deref()).await?;
+    let created = mux.set(&name, session, || setup.options(config)).await?;
+
+    let context = Context { session: &name, path: session, previous };
+    if created {
+        hooks::run(&setup.hooks, Event::Create, context).await;
+    }
+    hooks::run(&setup.hooks, Event::Enter, context).await;
+
+    Ok(())
+}
+
+/// Where an entry of the [`open`] picker comes from.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Kind {
+    /// Session running in tmux.
+    Running,
+    /// Previously visited session.
+    History,
+    /// Directory discovered from the configured directories.
+    Directory,
+}
+
+impl Kind {
+    fn marker(self) -> &'static str {
+        match self {
+            Kind::Running => "[tmux]",
+            Kind::History => "[hist]",
+            Kind::Directory => "[dir] ",
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Entry {
+    kind: Kind,
+    /// Session directory, unknown for sessions created outside sessionizer.
+    path: Option<String>,
+    /// Tmux session name.
+    name: String,
+    /// Windows and clients of running sessions.
+    details: Option<String>,
+}
+
+impl Entry {
+    fn label(&self) -> String {
+        let label =
+            format!("{} {}", self.kind.marker(), self.path.as_deref().unwrap_or(&self.name));
+        match &self.details {
+            Some(details) => format!("{}  ({})", label, details),
+            None => label,
+        }
+    }
+}
+
+/// Describes the windows and clients of a running `session`, and its directory when it isn't
+/// linked to a known path.
+fn details(session: &TmuxSession, linked: bool) -> String {
+    let plural = if session.windows == 1 { "" } else { "s" };
+    let mut details = format!("{} window{}", session.windows, plural);
+    if session.attached > 0 {
+        details.push_str(", attached");
+    }
+    if !linked && !session.path.is_empty() {
+        details.push_str(&format!(", in {}", session.path));
+    }
+    details
+}
+
+/// Merges the `running` tmux sessions, the `history` and the discovered `directories` into one
+/// list without duplicated paths.
+///
+/// Running sessions come first, most recently active first, then the history and the
+/// directories in the given order. `name` returns the tmux session name of a path.
+fn entries(
+    running: &[TmuxSession],
+    history: &[String],
+    directories: &[String],
+    name: impl Fn(&str) -> String,
+) -> Vec<Entry> {
+    let mut entries: Vec<Entry> = Vec::new();
+    let known = || history.iter().chain(directories.iter());
+
+    let mut running: Vec<&TmuxSession> = running.iter().collect();
+    running.sort_by_key(|session| std::cmp::Reverse((session.activity, session.created)));
+    for session in running {
+        let path = known().find(|path| name(path) == session.name).cloned();
+        let details = Some(details(session, path.is_some()));
+        entries.push(Entry { kind: Kind::Running, path, name: session.name.clone(), details });
+    }
+
+    let rest = history
+        .iter()
+        .map(|path| (Kind::History, path))
+        .chain(directories.iter().map(|path| (Kind::Directory, path)));
+    for (kind, path) in rest {
+        if entries.iter().any(|e| e.path.as_ref() == Some(path)) {
+            continue;
+        }
+        entries.push(Entry { kind, path: Some(path.clone()), name: name(path), details: None });
+    }
+
+    entries
+}
+
+/// Adapts the configured `preview` to the [`Entry::label`] items, so `{}` stands for the path or
+/// the name of the entry rather than for its label.
+fn label_preview(preview: &str) -> String {
+    // Labels start with a 7 characters marker and running sessions end with their details.
+    let entry = r#""$(printf '%s' {} | cut -c 8- | sed 's/  ([^)]*)$//')""#;
+    preview.replace("{}", entry)
+}
+
+/// Picks a running session, a previous session or a directory, and moves to it.
+pub async fn open(mux: &impl Multiplexer, config: &Config, state: State) -> Result<()> {
+    let running = if mux.is_active().await? { mux.sessions().await? } else { vec![] };
+    let directories = crate::directories::candidates(config).await?;
+    let history = state.ranked(state.history.iter().rev());
+    let directories = state.ranked(&directories);
+    let entries = entries(&running, &history, &directories, |path| name_of(config, &state, path));
+
+    if entries.is_empty() {
+        println!("No sessions nor directories to open.");
+        return Ok(());
+    }
+
+    let request = picker::Request {
+        header: "Select a session or a directory to open, CTRL-T creates a worktree of the \
+            selected repository"
+            .into(),
+        items: entries.iter().map(Entry::label).collect(),
+        preview: config.preview.as_deref().map(label_preview),
+        expect: vec![picker::NEW_WORKTREE.into()],
+        ..Default::default()
+    };
+    let selection = picker::pick(config.picker, request).await?;
+    let label = selection.items.first().cloned();
+    let mut entry = entries
+        .into_iter()
+        .find(|e| Some(e.label()) == label)
+        .wrap_err("fail to find the selected entry")?;
+
+    if selection.key.as_deref() == Some(picker::NEW_WORKTREE) {
+        let repo = entry.path.wrap_err("the selected session has no directory")?;
+        let path = crate::worktree::ask_and_create(&config.worktree, &repo).await?;
+        let name = name_of(config, &state, &path);
+        entry = Entry { kind: Kind::Directory, name, path: Some(path), details: None };
+    }
+
+    enter(mux, config, state, &entry).await
+}
+
+/// Moves the client to the session of `entry`, creating it if needed.
+async fn enter(
+    mux: &impl Multiplexer,
+    config: &Config,
+    mut state: State,
+    entry: &Entry,
+) -> Result<()> {
+    let Some(path) = &entry.path else {
+        // Sessions created outside sessionizer have no directory to create them from.
+        if mux.is_active().await? {
+            mux.switch_client(&entry.name).await?;
+        } else {
+            mux.attach(&entry.name).await?;
+        }
+        return Ok(());
+    };
+
+    if !std::path::Path::new(path).exists() {
+        bail!("the session does not exists as a directory in the fs");
+    }
+
+    let previous = state.history.last().cloned();
+    switch(mux, config, &mut state, path, previous.as_deref()).await?;
+
+    state.update(|state| state.visit(path))?;
+
+    Ok(())
+}
+
+pub async fn history(state: State) -> Result<()> {
+    println!("{}", state.history.join("\n"));
+
+    Ok(())
+}
+
+/// Kills the tmux session `name` rooted at `path` and runs its kill hooks, including the ones of
+/// its project.
+async fn kill_session(
+    mux: &impl Multiplexer,
+    config: &Config,
+    name: &str,
+    path: &str,
+) -> Result<()> {
+    let hooks = resolve(config, path)?.hooks;
+    mux.kill_session(name).await?;
+
+    let context = Context { session: name, path, previous: None };
+    hooks::run(&hooks, Event::Kill, context).await;
+
+    Ok(())
+}
+
+/// Kills the tmux sessions of `sessions`, given as directories or names, and removes them from
+/// the history.
+///
+/// When the current session is one of them, the client first moves to the most recent session
+/// left, and nothing is killed if there's none.
+pub async fn kill(
+    mux: &impl Multiplexer,
+    config: &Config,
+    mut state: State,
+    sessions: Vec<String>,
+) -> Result<()> {
+    let sessions = if sessions.is_empty() {
+        if state.history.is_empty() {
+            println!("No sessions in the history.");
+            return Ok(());
+        }
+
+        let request = picker::Request {
+            header: "Select the sessions to kill, TAB selects several".into(),
+            items: state.ranked(state.history.iter().rev()),
+            multi: true,
+            preview: config.preview.clone(),
+            ..Default::default()
+        };
+        picker::pick(config.picker, request).await?.items
+    } else {
+        sessions
+    };
+
+    // Sessionizer sessions are given by directory, other ones by name.
+    let targets: Vec<(String, Option<String>)> = sessions
+        .iter()
+        .map(|session| session.trim())
+        .map(|session| match state.history.iter().any(|path| path == session) {
+            true => (name_of(config, &state, session), Some(session.to_string())),
+            false => (session.to_string(), path_of(config, &state, session)),
+        })
+        .collect();
+    let targeted = |path: &String| targets.iter().any(|(_, target)| target.as_ref() == Some(path));
+
+    let current = mux.current_session().await?;
+    if let Some(current) = current.filter(|current| targets.iter().any(|(name, _)| name == current))
+    {
+        let Some(next) = state.history.iter().rev().find(|path| !targeted(path)).cloned() else {
+            println!("Cannot kill the current session without another session to switch to.");
+            return Ok(());
+        };
+
+        let previous = path_of(config, &state, &current);
+        switch(mux, config, &mut state, &next, previous.as_deref()).await?;
+        state.update(|state| state.visit(&next))?;
+    }
+
+    for (name, path) in &targets {
+        if !mux.has_session(name).await? {
+            println!("Session {} is not running.", name);
+            continue;
+        }
+        kill_session(mux, config, name, path.as_deref().unwrap_or(name)).await?;
+        println!("Session {} killed.", name);
+    }
+
+    state.update(|state| state.history.retain(|path| !targeted(path)))?;
+
+    for path in targets.iter().filter_map(|(_, path)| path.as_deref()) {
+        offer_worktree_removal(config, path).await?;
+    }
+
+    Ok(())
+}
+
+/// Removes the stale entries of the history, or only prints them with `dry_run`.
+pub async fn prune(config: &Config, mut state: State, dry_run: bool) -> Result<()> {
+    let now = crate::state::now();
+    let stale = match dry_run {
+        true => state.stale(&config.history, now),
+        false => state.update(|state| state.prune(&config.history, now))?,
+    };
+
+    if stale.is_empty() {
+        println!("Nothing to prune.");
+    }
+    for (path, reason) in stale {
+        println!("{:<9}{}", reason, path);
+    }
+
+    Ok(())
+}
+
+/// Output format of `sessions ls`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, ValueEnum)]
+pub enum Format {
+    /// Aligned columns for humans.
+    #[default]
+    Table,
+    Json,
+    Yaml,
+    /// Tab separated values, with a header line.
+    Tsv,
+}
+
+/// Session described by `sessions ls`.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+struct Listing {
+    name: String,
+    path: String,
+    /// Whether the tmux session is running.
+    running: bool,
+    /// Number of attached clients.
+    attached: usize,
+    windows: usize,
+    /// Unix timestamp of the last activity of the running session, in seconds.
+    activity: Option<u64>,
+    /// Git branch checked out in the session directory.
+    branch: Option<String>,
+}
+
+/// Describes the sessions of the history along with the `running` tmux sessions, followed by
+/// the running sessions missing from the history, most recently active first.
+fn listings(config: &Config, state: &State, running: &[TmuxSession]) -> Vec<Listing> {
+    let mut listings: Vec<Listing> = state
+        .history
+        .iter()
+        .map(|path| {
+            let name = name_of(config, state, path);
+            let session = running.iter().find(|session| session.name == name);
+            Listing {
+                name,
+                path: path.clone(),
+                running: session.is_some(),
+                attached: session.map_or(0, |s| s.attached),
+                windows: session.map_or(0, |s| s.windows),
+                activity: session.map(|s| s.activity),
+                branch: crate::worktree::current_branch(path),
+            }
+        })
+        .collect();
+
+    let mut others: Vec<&TmuxSession> = running
+        .iter()
+        .filter(|session| !listings.iter().any(|listing| listing.name == session.name))
+        .collect();
+    others.sort_by_key(|session| std::cmp::Reverse((session.activity, session.created)));
+    for session in others {
+        let path = session.source.clone().unwrap_or_else(|| session.path.clone());
+        listings.push(Listing {
+            name: session.name.clone(),
+            branch: crate::worktree::current_branch(&path),
+            path,
+            running: true,
+            attached: session.attached,
+            windows: session.windows,
+            activity: Some(session.activity),
+        });
+    }
+
+    listings
+}
+
+/// Formats a duration in seconds the way humans read it, e.g. `3h ago`.
+pub fn ago(seconds: u64) -> String {
+    const MINUTE: u64 = 60;
+    const HOUR: u64 = 60 * MINUTE;
+    const DAY: u64 = 24 * HOUR;
+
+    match seconds {
+        s if s < MINUTE => "just now".into(),
+        s if s < HOUR => format!("{}m ago", s / MINUTE),
+        s if s < DAY => format!("{}h ago", s / HOUR),
+        s => format!("{}d ago", s / DAY),
     }
 }
 
-pub async fn history(config: Config) -> Result<()> {
-    println!("{}", config.sessions.join("\n"));
+/// Renders the `listings` in `format`, relative times being computed from `now`.
+fn render(listings: &[Listing], format: Format, now: u64) -> Result<String> {
+    const HEADER: [&str; 7] =
+        ["NAME", "PATH", "RUNNING", "ATTACHED", "WINDOWS", "ACTIVITY", "BRANCH"];
+
+    match format {
+        Format::Json => {
+            serde_json::to_string_pretty(listings).wrap_err("fail to serialize the sessions")
+        }
+        Format::Yaml => serde_yaml::to_string(listings).wrap_err("fail to serialize the sessions"),
+        Format::Tsv => {
+            let mut lines = vec![HEADER.join("\t").to_lowercase()];
+            for listing in listings {
+                let fields = [
+                    listing.name.clone(),
+                    listing.path.clone(),
+                    listing.running.to_string(),
+                    listing.attached.to_string(),
+                    listing.windows.to_string(),
+                    listing.activity.map(|a| a.to_string()).unwrap_or_default(),
+                    listing.branch.clone().unwrap_or_default(),
+                ];
+                lines.push(fields.join("\t"));
+            }
+            Ok(lines.join("\n"))
+        }
+        Format::Table => {
+            let mut rows = vec![HEADER.map(String::from)];
+            for listing in listings {
+                let live =
+                    |value: usize| if listing.running { value.to_string() } else { "-".into() };
+                rows.push([
+                    listing.name.clone(),
+                    listing.path.clone(),
+                    if listing.running { "yes" } else { "no" }.into(),
+                    live(listing.attached),
+                    live(listing.windows),
+                    listing.activity.map_or("-".into(), |a| ago(now.saturating_sub(a))),
+                    listing.branch.clone().unwrap_or_else(|| "-".into()),
+                ]);
+            }
+
+            let mut widths = [0; HEADER.len()];
+            for row in &rows {
+                for (width, cell) in widths.iter_mut().zip(row) {
+                    *width = (*width).max(cell.chars().count());
+                }
+            }
+
+            let lines: Vec<String> = rows
+                .iter()
+                .map(|row| {
+                    let cells: Vec<String> = row
+                        .iter()
+                        .zip(widths)
+                        .map(|(cell, width)| format!("{:<1$}", cell, width))
+                        .collect();
+                    cells.join("  ").trim_end().to_string()
+                })
+                .collect();
+            Ok(lines.join("\n"))
+        }
+    }
+}
+
+/// Prints the sessions of the history with their tmux and git status.
+pub async fn ls(
+    mux: &impl Multiplexer,
+    config: &Config,
+    state: State,
+    format: Format,
+) -> Result<()> {
+    let running = if mux.is_active().await? { mux.sessions().await? } else { vec![] };
+    let now = crate::state::now();
+
+    println!("{}", render(&listings(config, &state, &running), format, now)?.trim_end());
 
     Ok(())
 }
 
-pub async fn new(mut config: Config, session: Option<String>) -> Result<()> {
+pub async fn new(
+    mux: &impl Multiplexer,
+    config: &Config,
+    mut state: State,
+    session: Option<String>,
+) -> Result<()> {
     let session = if session.is_none() {
-        if config.sessions.is_empty() {
+        if state.history.is_empty() {
             println!("No sessions in the history.");
             return Ok(());
         }
 
-        Some(fzf::directories(&config).await?)
+        let selection = picker::directories(config, &state).await?;
+        let create = selection.key.as_deref() == Some(picker::NEW_WORKTREE);
+        let directory = selection.first()?;
+        match create {
+            true => Some(crate::worktree::ask_and_create(&config.worktree, &directory).await?),
+            false => Some(directory),
+        }
     } else {
         session
     }
@@ -116,23 +723,27 @@ pub async fn new(mut config: Config, session: Option<String>) -> Result<()> {
         bail!("the session does not exists as a directory in the fs");
     }
 
-    tmux::set(session).await?;
+    let previous = state.history.last().cloned();
+    switch(mux, config, &mut state, session, previous.as_deref()).await?;
 
-    config.sessions.retain(|s| s != session);
-    config.sessions.push(String::from(session));
-    config.save()?;
+    state.update(|state| state.visit(session))?;
 
     Ok(())
 }
 
-pub async fn go(mut config: Config, session: Option<String>) -> Result<()> {
+pub async fn go(
+    mux: &impl Multiplexer,
+    config: &Config,
+    mut state: State,
+    session: Option<String>,
+) -> Result<()> {
     let session = if session.is_none() {
-        if config.sessions.is_empty() {
+        if state.history.is_empty() {
             println!("No sessions in the history.");
             return Ok(());
         }
 
-        Some(fzf::sessions(config.sessions.clone()).await?)
+        Some(picker::sessions(config, &state).await?)
     } else {
         session
     }
@@ -145,33 +756,41 @@ pub async fn go(mut config: Config, session: Option<String>) -> Result<()> {
         bail!("the session does not exists as a directory in the fs");
     }
 
-    if !config.sessions.iter().any(|s| s == session) {
+    if !state.history.iter().any(|s| s == session) {
         println!("Session not found in the history.");
         return Ok(());
     }
 
-    tmux::set(session).await?;
+    let previous = state.history.last().cloned();
+    switch(mux, config, &mut state, session, previous.as_deref()).await?;
 
-    config.sessions.retain(|s| s != session);
-    config.sessions.push(String::from(session));
-    config.save()?;
+    state.update(|state| state.visit(session))?;
 
     Ok(())
 }
 
-pub async fn add(mut config: Config, session: String, set: bool) -> Result<()> {
-    if config.sessions.contains(&session) {
+pub async fn add(
+    mux: &impl Multiplexer,
+    config: &Config,
+    mut state: State,
+    session: String,
+    set: bool,
+) -> Result<()> {
+    if state.history.contains(&session) {
         println!("Session already exists in the history.");
         return Ok(());
     }
 
-    tmux::new_session(&session).await?;
-    config.sessions.push(session.clone());
-
-    config.save()?;
+    create(mux, config, &mut state, &session).await?;
+    let previous = state.history.last().cloned();
+    state.update(|state| {
+        if !state.history.contains(&session) {
+            state.history.push(session.clone());
+        }
+    })?;
 
     if set {
-        tmux::set(&session).await?;
+        switch(mux, config, &mut state, &session, previous.as_deref()).await?;
     }
 
     println!("Session {} added to the history.", &session);
@@ -179,114 +798,790 @@ pub async fn add(mut config: Config, session: String, set: bool) -> Result<()> {
     Ok(())
 }
 
-pub async fn remove(mut config: Config, session: String) -> Result<()> {
+pub async fn remove(
+    mux: &impl Multiplexer,
+    config: &Config,
+    mut state: State,
+    session: String,
+) -> Result<()> {
     // If we are currently on the session to be removed then bail
-    if tmux::current_session().await? == session {
+    if mux.current_session().await? == Some(name_of(config, &state, &session)) {
         println!("Cannot remove the current session.");
         return Ok(());
     }
 
     // Remove the `session` from the `history`.
-    config.sessions.retain(|s| s != &session);
-
-    config.save()?;
+    state.update(|state| state.history.retain(|s| s != &session))?;
 
     Ok(())
 }
 
-pub async fn sync(config: Config, reverse: bool) -> Result<()> {
-    match reverse {
-        true => sync_from_tmux(config).await,
-        false => sync_to_tmux(config).await,
+/// Offers to remove the linked worktree at `path` once its session is killed, when the
+/// `worktree.remove` option is set.
+async fn offer_worktree_removal(config: &Config, path: &str) -> Result<()> {
+    if config.worktree.remove
+        && crate::worktree::name(path).is_some()
+        && crate::prompt::is_interactive()
+    {
+        crate::worktree::offer_removal(path).await?;
     }
+
+    Ok(())
 }
 
-pub async fn sync_to_tmux(config: Config) -> Result<()> {
-    let sessions = tmux::ls().await?;
+/// Syncs tmux to the history, or the history from tmux when `reverse` is set, after printing
+/// the plan and asking for confirmation unless `yes` is set.
+pub async fn sync(
+    mux: &impl Multiplexer,
+    config: &Config,
+    state: State,
+    reverse: bool,
+    dry_run: bool,
+    yes: bool,
+) -> Result<()> {
+    let running = mux.sessions().await?;
+    let steps = match reverse {
+        true => sync::from_tmux(config, &state, &running)?,
+        false => sync::to_tmux(config, &state, &running)?,
+    };
 
-    for session in sessions {
-        if !config.sessions.contains(&session) {
-            tmux::kill_session(&session).await?;
-        }
+    if !steps.iter().any(|step| step.action.changes()) {
+        println!("Nothing to sync.");
+        return Ok(());
     }
 
-    for session in &config.sessions {
-        if tmux::has_session(session).await? {
-            continue;
+    for step in &steps {
+        println!("{}", step);
+    }
+
+    if dry_run {
+        return Ok(());
+    }
+
+    if !yes {
+        if !crate::prompt::is_interactive() {
+            bail!("fail to confirm the sync, pass --yes to apply it without a terminal");
         }
+        if !crate::prompt::confirm("Apply these changes?")? {
+            println!("Sync cancelled.");
+            return Ok(());
+        }
+    }
 
-        tmux::new_session(session).await?;
+    match reverse {
+        true => sync_from_tmux(mux, config, state, &steps).await,
+        false => sync_to_tmux(mux, config, state, &steps).await,
+    }
+}
+
+async fn sync_to_tmux(
+    mux: &impl Multiplexer,
+    config: &Config,
+    mut state: State,
+    steps: &[Step],
+) -> Result<()> {
+    for step in steps {
+        match (step.action, &step.path) {
+            (Action::Create | Action::Keep, Some(path)) => {
+                create(mux, config, &mut state, path).await?;
+            }
+            (Action::Kill, path) => {
+                kill_session(mux, config, &step.name, path.as_deref().unwrap_or(&step.name))
+                    .await?;
+            }
+            _ => {}
+        }
     }
 
     Ok(())
 }
 
-pub async fn sync_from_tmux(mut config: Config) -> Result<()> {
-    let tmux_sessions = tmux::ls().await?;
+async fn sync_from_tmux(
+    mux: &impl Multiplexer,
+    config: &Config,
+    mut state: State,
+    steps: &[Step],
+) -> Result<()> {
+    let history = sync::history(steps, &state.history);
 
-    config.sessions = tmux_sessions;
+    // Get a copy of the last element of the tmux sessions
+    let last = steps
+        .iter()
+        .rev()
+        .find(|step| matches!(step.action, Action::Add | Action::Adopt | Action::Keep))
+        .and_then(|step| step.path.clone())
+        .wrap_err("fail to get the last session")?;
 
-    // Get a copy of the last element of the config.sessions array
-    let last = config.sessions.last().cloned().wrap_err("fail to get the last session")?;
-
-    tmux::set(&last).await?;
+    // Name the directories after their running sessions, so they're found again.
+    state.update(|state| {
+        for step in steps.iter().filter(|step| matches!(step.action, Action::Add | Action::Adopt)) {
+            if let Some(path) = &step.path {
+                state.names.entry(path.clone()).or_insert_with(|| step.name.clone());
+            }
+        }
+        state.history = history;
+    })?;
 
-    config.save()?;
+    switch(mux, config, &mut state, &last, None).await?;
 
     Ok(())
 }
 
-pub async fn next(mut config: Config, show: bool) -> Result<()> {
-    if config.sessions.is_empty() {
+pub async fn next(
+    mux: &impl Multiplexer,
+    config: &Config,
+    mut state: State,
+    show: bool,
+) -> Result<()> {
+    if state.history.is_empty() {
         println!("No more sessions in the history.");
         return Ok(());
     }
 
-    if config.sessions.len() == 1 {
+    if state.history.len() == 1 {
         println!("Only one session in the history.");
         return Ok(());
     }
 
-    // Pop the first `session` inside `config.sessions`
-    let session = config.sessions.remove(0);
+    let current = state.history.last().cloned();
+    // Pop the first `session` inside `state.history`
+    let session = state.history.remove(0);
 
     if show {
         println!("Next session: {}", session);
         return Ok(());
     }
 
-    config.sessions.push(session.clone());
-    tmux::set(&session).await?;
+    switch(mux, config, &mut state, &session, current.as_deref()).await?;
 
-    config.save()?;
+    state.update(|state| state.visit(&session))?;
 
     Ok(())
 }
 
-pub async fn previous(mut config: Config, show: bool) -> Result<()> {
-    if config.sessions.is_empty() {
+pub async fn previous(
+    mux: &impl Multiplexer,
+    config: &Config,
+    mut state: State,
+    show: bool,
+) -> Result<()> {
+    if state.history.is_empty() {
         println!("No more sessions in the history.");
         return Ok(());
     }
 
-    if config.sessions.len() == 1 {
+    if state.history.len() == 1 {
         println!("Only one session in the history.");
         return Ok(());
     }
 
-    let current = config.sessions.pop().wrap_err("fail to get the current session")?;
-    let prev = config.sessions.last().cloned().wrap_err("fail to get the previous session")?;
+    let current = state.history.pop().wrap_err("fail to get the current session")?;
+    let prev = state.history.last().cloned().wrap_err("fail to get the previous session")?;
 
     if show {
         println!("Previous session: {}", prev);
         return Ok(());
     }
 
-    tmux::set(&prev).await?;
-    // Append the `current` session to the beginning of the `config.sessions` vector.
-    config.sessions.insert(0, current);
+    switch(mux, config, &mut state, &prev, Some(&current)).await?;
 
-    config.save()?;
+    // Move the `current` session to the beginning of the `state.history` vector.
+    state.update(|state| {
+        state.history.retain(|s| s != &current);
+        state.history.insert(0, current);
+    })?;
 
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tmux::fake::Fake;
+
+    /// Creates a configuration and a state file inside `dir` with one existing directory per
+    /// `name` in the history.
+    fn setup(dir: &tempfile::TempDir, names: &[&str]) -> (Config, State, Vec<String>) {
+        let config = Config::new(&dir.path().join("sessionizer.yaml").to_string_lossy());
+        config.save().unwrap();
+        let mut state = State::new(&path(dir));
+        let sessions: Vec<String> = names
+            .iter()
+            .map(|name| {
+                let session = dir.path().join(name);
+                std::fs::create_dir(&session).unwrap();
+                session.to_string_lossy().to_string()
+            })
+            .collect();
+        state.history = sessions.clone();
+        state.save().unwrap();
+        (config, state, sessions)
+    }
+
+    fn path(dir: &tempfile::TempDir) -> String {
+        dir.path().join("state.json").to_string_lossy().to_string()
+    }
+
+    fn load(dir: &tempfile::TempDir) -> State {
+        State::load(&path(dir)).unwrap()
+    }
+
+    fn reload(dir: &tempfile::TempDir) -> Vec<String> {
+        load(dir).history
+    }
+
+    /// Writes and trusts a project configuration inside `session` whose `on_kill` hook logs the
+    /// killed path into `log`.
+    fn kill_hook(config: &Config, session: &str, log: &std::path::Path) {
+        let hook = format!("echo $SESSIONIZER_EVENT $SESSIONIZER_PATH >> {}", log.display());
+        let yaml = format!("hooks:\n  on_kill:\n    - \"{}\"\n", hook);
+        std::fs::write(std::path::Path::new(session).join(".sessionizer.yaml"), yaml).unwrap();
+
+        let mut trust = crate::project::Trust::load(config.path()).unwrap();
+        trust.allow(session).unwrap();
+        trust.save().unwrap();
+    }
+
+    #[tokio::test]
+    async fn next_rotates_the_oldest_session_to_the_end() {
+        let dir = tempfile::tempdir().unwrap();
+        let (config, state, sessions) = setup(&dir, &["a", "b", "c"]);
+        let mux = Fake::with_sessions(&[]);
+
+        next(&mux, &config, state, false).await.unwrap();
+
+        let history = reload(&dir);
+        assert_eq!(history, vec![sessions[1].clone(), sessions[2].clone(), sessions[0].clone()]);
+        assert_eq!(mux.state().current, Some(sessions[0].clone()));
+    }
+
+    #[tokio::test]
+    async fn next_and_previous_are_inverse() {
+        let dir = tempfile::tempdir().unwrap();
+        let (config, state, sessions) = setup(&dir, &["a", "b", "c"]);
+        let mux = Fake::with_sessions(&[]);
+
+        next(&mux, &config, state, false).await.unwrap();
+        previous(&mux, &config, load(&dir), false).await.unwrap();
+
+        assert_eq!(reload(&dir), sessions);
+        assert_eq!(mux.state().current, Some(sessions[2].clone()));
+    }
+
+    #[tokio::test]
+    async fn previous_moves_the_current_session_to_the_front() {
+        let dir = tempfile::tempdir().unwrap();
+        let (config, state, sessions) = setup(&dir, &["a", "b", "c"]);
+        let mux = Fake::with_sessions(&[]);
+
+        previous(&mux, &config, state, false).await.unwrap();
+
+        let history = reload(&dir);
+        assert_eq!(history, vec![sessions[2].clone(), sessions[0].clone(), sessions[1].clone()]);
+        assert_eq!(mux.state().current, Some(sessions[1].clone()));
+    }
+
+    #[tokio::test]
+    async fn show_does_not_switch_nor_save() {
+        let dir = tempfile::tempdir().unwrap();
+        let (config, state, sessions) = setup(&dir, &["a", "b"]);
+        let mux = Fake::default();
+
+        next(&mux, &config, state, true).await.unwrap();
+        previous(&mux, &config, load(&dir), true).await.unwrap();
+
+        assert_eq!(reload(&dir), sessions);
+        assert!(mux.state().calls.is_empty());
+    }
+
+    #[tokio::test]
+    async fn go_moves_the_session_to_the_end_of_the_history() {
+        let dir = tempfile::tempdir().unwrap();
+        let (config, state, sessions) = setup(&dir, &["a", "b", "c"]);
+        let mux = Fake::with_sessions(&[]);
+
+        go(&mux, &config, state, Some(sessions[0].clone())).await.unwrap();
+
+        let history = reload(&dir);
+        assert_eq!(history, vec![sessions[1].clone(), sessions[2].clone(), sessions[0].clone()]);
+        assert_eq!(mux.state().sessions, vec![sessions[0].clone()]);
+        assert_eq!(load(&dir).visits[&sessions[0]].rank, 1.0);
+    }
+
+    #[tokio::test]
+    async fn go_fails_for_missing_directories() {
+        let dir = tempfile::tempdir().unwrap();
+        let (config, state, _) = setup(&dir, &["a"]);
+        let missing = dir.path().join("missing").to_string_lossy().to_string();
+
+        assert!(go(&Fake::default(), &config, state, Some(missing)).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn new_creates_the_session_and_records_it() {
+        let dir = tempfile::tempdir().unwrap();
+        let (config, state, sessions) = setup(&dir, &["a.b"]);
+        let other = dir.path().join("c");
+        std::fs::create_dir(&other).unwrap();
+        let other = other.to_string_lossy().to_string();
+        let mux = Fake::default();
+
+        new(&mux, &config, state, Some(other.clone())).await.unwrap();
+
+        assert_eq!(reload(&dir), vec![sessions[0].clone(), other.clone()]);
+        let state = mux.state();
+        assert_eq!(state.sessions, vec![other.clone()]);
+        assert_eq!(state.calls.last().unwrap(), &format!("attach {}", other));
+    }
+
+    #[tokio::test]
+    async fn new_sessions_get_the_configured_environment() {
+        let dir = tempfile::tempdir().unwrap();
+        let (mut config, state, sessions) = setup(&dir, &["a"]);
+        config.env = vec!["AWS_PROFILE=default".into(), "EDITOR=vim".into()];
+        config.directories = vec![crate::directories::Directory {
+            id: "id".into(),
+            path: dir.path().to_string_lossy().to_string(),
+            mindepth: 1,
+            maxdepth: 1,
+            env: vec!["AWS_PROFILE=work".into()],
+            ..Default::default()
+        }];
+        let mux = Fake::default();
+
+        go(&mux, &config, state, Some(sessions[0].clone())).await.unwrap();
+
+        assert_eq!(mux.state().env[&sessions[0]], vec!["AWS_PROFILE=work", "EDITOR=vim"]);
+    }
+
+    #[tokio::test]
+    async fn invalid_env_entries_dont_prevent_switching() {
+        let dir = tempfile::tempdir().unwrap();
+        let (mut config, state, sessions) = setup(&dir, &["a", "b"]);
+        config.env = vec!["MISSING=$SESSIONIZER_UNDEFINED_VARIABLE".into(), "EDITOR=vim".into()];
+        let mux = Fake::default();
+
+        go(&mux, &config, state, Some(sessions[0].clone())).await.unwrap();
+        go(&mux, &config, load(&dir), Some(sessions[1].clone())).await.unwrap();
+        go(&mux, &config, load(&dir), Some(sessions[0].clone())).await.unwrap();
+
+        assert_eq!(mux.state().env[&sessions[0]], vec!["EDITOR=vim"]);
+        assert_eq!(mux.state().current.as_deref(), Some(sessions[0].as_str()));
+    }
+
+    #[tokio::test]
+    async fn trusted_project_configs_are_merged_over_the_global_one() {
+        let dir = tempfile::tempdir().unwrap();
+        let (mut config, state, sessions) = setup(&dir, &["a"]);
+        config.env = vec!["AWS_PROFILE=default".into()];
+        let project = std::path::Path::new(&sessions[0]).join(".sessionizer.yaml");
+        std::fs::write(&project, "env: [AWS_PROFILE=project]").unwrap();
+        let mux = Fake::default();
+
+        go(&mux, &config, state, Some(sessions[0].clone())).await.unwrap();
+        assert_eq!(mux.state().env[&sessions[0]], vec!["AWS_PROFILE=default"]);
+        mux.kill_session(&sessions[0]).await.unwrap();
+
+        let mut trust = crate::project::Trust::load(config.path()).unwrap();
+        trust.allow(&sessions[0]).unwrap();
+        trust.save().unwrap();
+        go(&mux, &config, load(&dir), Some(sessions[0].clone())).await.unwrap();
+
+        assert_eq!(mux.state().env[&sessions[0]], vec!["AWS_PROFILE=project"]);
+    }
+
+    #[tokio::test]
+    async fn switching_runs_the_lifecycle_hooks() {
+        let dir = tempfile::tempdir().unwrap();
+        let (mut config, state, sessions) = setup(&dir, &["a", "b"]);
+        let log = dir.path().join("log");
+        let hook = format!("echo $SESSIONIZER_EVENT $SESSIONIZER_PATH >> {}", log.display());
+        config.hooks = Hooks {
+            on_create: vec![hook.clone()],
+            on_enter: vec![hook.clone()],
+            on_leave: vec![hook],
+            ..Default::default()
+        };
+        let mux = Fake::with_sessions(&[&sessions[1]]);
+
+        next(&mux, &config, state, false).await.unwrap();
+
+        assert_eq!(
+            std::fs::read_to_string(log).unwrap(),
+            format!(
+                "on_leave {}\non_create {}\non_enter {}\n",
+                sessions[1], sessions[0], sessions[0]
+            )
+        );
+    }
+
+    #[tokio::test]
+    async fn remove_refuses_the_current_session() {
+        let dir = tempfile::tempdir().unwrap();
+        let (config, _, sessions) = setup(&dir, &["a", "b"]);
+        let mux = Fake::with_sessions(&["a", "b"]);
+
+        remove(&mux, &config, load(&dir), "b".to_string()).await.unwrap();
+        assert_eq!(reload(&dir), sessions);
+
+        remove(&mux, &config, load(&dir), sessions[0].clone()).await.unwrap();
+        assert_eq!(reload(&dir), vec![sessions[1].clone()]);
+    }
+
+    #[tokio::test]
+    async fn remove_finds_the_current_session_by_its_name() {
+        let dir = tempfile::tempdir().unwrap();
+        let (config, mut state, sessions) = setup(&dir, &["a", "b"]);
+        state.update(|state| state.names.insert(sessions[1].clone(), "b".into())).unwrap();
+        let mux = Fake::with_sessions(&["b"]);
+
+        remove(&mux, &config, load(&dir), sessions[1].clone()).await.unwrap();
+
+        assert_eq!(reload(&dir), sessions);
+    }
+
+    #[tokio::test]
+    async fn sessions_are_named_once_with_the_configured_naming() {
+        let dir = tempfile::tempdir().unwrap();
+        let (mut config, state, _) = setup(&dir, &["work", "home"]);
+        config.naming = naming::Naming::Basename;
+        let paths: Vec<String> = ["work/api", "home/api"]
+            .iter()
+            .map(|path| {
+                std::fs::create_dir(dir.path().join(path)).unwrap();
+                dir.path().join(path).to_string_lossy().to_string()
+            })
+            .collect();
+        let mux = Fake::default();
+
+        new(&mux, &config, state, Some(paths[0].clone())).await.unwrap();
+        new(&mux, &config, load(&dir), Some(paths[1].clone())).await.unwrap();
+        config.naming = naming::Naming::Parent;
+        go(&mux, &config, load(&dir), Some(paths[0].clone())).await.unwrap();
+
+        assert_eq!(mux.state().sessions, vec!["api", "home/api"]);
+        assert_eq!(mux.state().current.as_deref(), Some("api"));
+        assert_eq!(load(&dir).names[&paths[1]], "home/api");
+    }
+
+    #[tokio::test]
+    async fn sessions_are_not_named_after_running_foreign_sessions() {
+        let dir = tempfile::tempdir().unwrap();
+        let (mut config, state, _) = setup(&dir, &["src"]);
+        config.naming = naming::Naming::Basename;
+        std::fs::create_dir(dir.path().join("src/api")).unwrap();
+        let path = dir.path().join("src/api").to_string_lossy().to_string();
+        let mux = Fake::with_sessions(&["api"]);
+
+        new(&mux, &config, state, Some(path.clone())).await.unwrap();
+
+        assert_eq!(mux.state().sessions, vec!["api", "src/api"]);
+        assert_eq!(mux.state().current.as_deref(), Some("src/api"));
+        assert_eq!(load(&dir).names[&path], "src/api");
+    }
+
+    #[tokio::test]
+    async fn sync_to_tmux_kills_unknown_and_creates_missing_sessions() {
+        let dir = tempfile::tempdir().unwrap();
+        let (config, mut state, sessions) = setup(&dir, &["a", "b"]);
+        state.update(|state| state.names.insert(sessions[0].clone(), "first".into())).unwrap();
+        let mux = Fake::with_sessions(&["stray", "first"]);
+
+        sync(&mux, &config, state, false, false, true).await.unwrap();
+
+        assert_eq!(mux.state().sessions, vec!["first".to_string(), sessions[1].clone()]);
+    }
+
+    #[tokio::test]
+    async fn sync_from_tmux_replaces_the_history() {
+        let dir = tempfile::tempdir().unwrap();
+        let (config, mut state, sessions) = setup(&dir, &["a", "x", "y"]);
+        state
+            .update(|state| {
+                state.history.truncate(1);
+                state.names.insert(sessions[0].clone(), "a".into());
+            })
+            .unwrap();
+        let mux = Fake::with_sessions(&["a", "x", "y", "z"]);
+        mux.state().sources.insert("x".into(), sessions[1].clone());
+        mux.state().paths.insert("y".into(), sessions[2].clone());
+
+        sync(&mux, &config, state, true, false, true).await.unwrap();
+
+        assert_eq!(reload(&dir), sessions);
+        assert_eq!(load(&dir).path_of("y"), Some(sessions[2].as_str()));
+        assert_eq!(mux.state().current, Some("y".to_string()));
+    }
+
+    #[tokio::test]
+    async fn kill_stops_sessions_and_forgets_them() {
+        let dir = tempfile::tempdir().unwrap();
+        let (config, state, sessions) = setup(&dir, &["a", "b", "c"]);
+        let mux = Fake::with_sessions(&[&sessions[0], &sessions[1], "stray", &sessions[2]]);
+
+        let targets = vec![sessions[0].clone(), "stray".into(), "gone".into()];
+        kill(&mux, &config, state, targets).await.unwrap();
+
+        assert_eq!(mux.state().sessions, vec![sessions[1].clone(), sessions[2].clone()]);
+        assert_eq!(reload(&dir), vec![sessions[1].clone(), sessions[2].clone()]);
+    }
+
+    #[tokio::test]
+    async fn kill_moves_away_from_the_current_session() {
+        let dir = tempfile::tempdir().unwrap();
+        let (config, state, sessions) = setup(&dir, &["a", "b", "c"]);
+        let mux = Fake::with_sessions(&[&sessions[2]]);
+
+        kill(&mux, &config, state, vec![sessions[1].clone(), sessions[2].clone()]).await.unwrap();
+
+        assert_eq!(mux.state().sessions, vec![sessions[0].clone()]);
+        assert_eq!(mux.state().current, Some(sessions[0].clone()));
+        assert_eq!(reload(&dir), vec![sessions[0].clone()]);
+    }
+
+    #[tokio::test]
+    async fn kill_refuses_the_last_session() {
+        let dir = tempfile::tempdir().unwrap();
+        let (config, state, sessions) = setup(&dir, &["a"]);
+        let mux = Fake::with_sessions(&[&sessions[0]]);
+
+        kill(&mux, &config, state, sessions.clone()).await.unwrap();
+
+        assert_eq!(mux.state().sessions, sessions);
+        assert_eq!(reload(&dir), sessions);
+    }
+
+    #[tokio::test]
+    async fn prune_removes_missing_directories() {
+        let dir = tempfile::tempdir().unwrap();
+        let (config, mut state, sessions) = setup(&dir, &["a", "b"]);
+        let gone = dir.path().join("gone").to_string_lossy().to_string();
+        state.update(|state| state.history.insert(1, gone)).unwrap();
+
+        prune(&config, load(&dir), true).await.unwrap();
+        assert_eq!(reload(&dir).len(), 3);
+
+        prune(&config, state, false).await.unwrap();
+        assert_eq!(reload(&dir), sessions);
+    }
+
+    #[tokio::test]
+    async fn sync_dry_run_changes_nothing() {
+        let dir = tempfile::tempdir().unwrap();
+        let (config, state, sessions) = setup(&dir, &["a"]);
+        let mux = Fake::with_sessions(&["stray"]);
+
+        sync(&mux, &config, state, false, true, false).await.unwrap();
+
+        assert_eq!(mux.state().sessions, vec!["stray"]);
+        sync(&mux, &config, load(&dir), true, true, false).await.unwrap();
+        assert_eq!(reload(&dir), sessions);
+    }
+
+    #[tokio::test]
+    async fn sync_runs_the_project_kill_hooks() {
+        let dir = tempfile::tempdir().unwrap();
+        let (config, mut state, sessions) = setup(&dir, &["a", "b"]);
+        state
+            .update(|state| {
+                state.history.truncate(1);
+                state.names.insert(sessions[1].clone(), "b".into());
+            })
+            .unwrap();
+        let log = dir.path().join("log");
+        kill_hook(&config, &sessions[1], &log);
+        let mux = Fake::with_sessions(&["b"]);
+
+        sync(&mux, &config, state, false, false, true).await.unwrap();
+
+        assert_eq!(mux.state().sessions, vec![sessions[0].clone()]);
+        assert_eq!(std::fs::read_to_string(log).unwrap(), format!("on_kill {}\n", sessions[1]));
+    }
+
+    #[tokio::test]
+    async fn sync_spares_protected_sessions() {
+        let dir = tempfile::tempdir().unwrap();
+        let (mut config, state, sessions) = setup(&dir, &["a"]);
+        config.sync.protect = vec!["scratch*".into()];
+        let mux = Fake::with_sessions(&["scratch", "stray"]);
+
+        sync(&mux, &config, state, false, false, true).await.unwrap();
+
+        assert_eq!(mux.state().sessions, vec!["scratch".to_string(), sessions[0].clone()]);
+    }
+
+    #[tokio::test]
+    async fn ls_describes_running_sessions() {
+        let dir = tempfile::tempdir().unwrap();
+        let (config, state, sessions) = setup(&dir, &["a", "b"]);
+        std::fs::create_dir(dir.path().join("b/.git")).unwrap();
+        std::fs::write(dir.path().join("b/.git/HEAD"), "ref: refs/heads/main\n").unwrap();
+        let mux = Fake::with_sessions(&[&sessions[1], "scratch"]);
+        mux.state().current = Some(sessions[1].clone());
+        let scratch = dir.path().join("b/docs").to_string_lossy().to_string();
+        std::fs::create_dir(&scratch).unwrap();
+        mux.state().paths.insert("scratch".into(), scratch.clone());
+
+        let running = mux.sessions().await.unwrap();
+        let listings = listings(&config, &state, &running);
+
+        assert_eq!(
+            listings,
+            vec![
+                Listing {
+                    name: sessions[0].clone(),
+                    path: sessions[0].clone(),
+                    running: false,
+                    attached: 0,
+                    windows: 0,
+                    activity: None,
+                    branch: None,
+                },
+                Listing {
+                    name: sessions[1].clone(),
+                    path: sessions[1].clone(),
+                    running: true,
+                    attached: 1,
+                    windows: 1,
+                    activity: Some(0),
+                    branch: Some("main".into()),
+                },
+                Listing {
+                    name: "scratch".into(),
+                    path: scratch,
+                    running: true,
+                    attached: 0,
+                    windows: 1,
+                    activity: Some(0),
+                    branch: Some("main".into()),
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn ls_renders_tables_and_tsv() {
+        let listings = vec![
+            Listing {
+                name: "api".into(),
+                path: "/src/api".into(),
+                running: true,
+                attached: 1,
+                windows: 3,
+                activity: Some(1000),
+                branch: Some("main".into()),
+            },
+            Listing {
+                name: "dotfiles".into(),
+                path: "/home/me/.dotfiles".into(),
+                running: false,
+                attached: 0,
+                windows: 0,
+                activity: None,
+                branch: None,
+            },
+        ];
+
+        assert_eq!(
+            render(&listings, Format::Table, 1000 + 2 * 60 * 60).unwrap(),
+            "NAME      PATH                RUNNING  ATTACHED  WINDOWS  ACTIVITY  BRANCH\n\
+             api       /src/api            yes      1         3        2h ago    main\n\
+             dotfiles  /home/me/.dotfiles  no       -         -        -         -"
+        );
+        assert_eq!(
+            render(&listings, Format::Tsv, 0).unwrap(),
+            "name\tpath\trunning\tattached\twindows\tactivity\tbranch\n\
+             api\t/src/api\ttrue\t1\t3\t1000\tmain\n\
+             dotfiles\t/home/me/.dotfiles\tfalse\t0\t0\t\t"
+        );
+
+        let json: serde_json::Value =
+            serde_json::from_str(&render(&listings, Format::Json, 0).unwrap()).unwrap();
+        assert_eq!(json[0]["windows"], 3);
+        assert_eq!(json[1]["branch"], serde_json::Value::Null);
+        let yaml: serde_yaml::Value =
+            serde_yaml::from_str(&render(&listings, Format::Yaml, 0).unwrap()).unwrap();
+        assert_eq!(yaml[0]["name"], "api");
+    }
+
+    #[test]
+    fn open_entries_are_deduplicated_by_path() {
+        let strings = |items: &[&str]| items.iter().map(|s| s.to_string()).collect::<Vec<_>>();
+        let running = vec![
+            TmuxSession { name: "/src/b".into(), windows: 1, activity: 1, ..Default::default() },
+            TmuxSession {
+                name: "scratch".into(),
+                path: "/tmp".into(),
+                attached: 1,
+                windows: 2,
+                activity: 2,
+                ..Default::default()
+            },
+        ];
+        let history = strings(&["/src/b", "/src/a"]);
+        let directories = strings(&["/src/a", "/src/b", "/src/c"]);
+
+        let labels: Vec<_> = entries(&running, &history, &directories, |path| path.to_string())
+            .iter()
+            .map(Entry::label)
+            .collect();
+
+        assert_eq!(
+            labels,
+            vec![
+                "[tmux] scratch  (2 windows, attached, in /tmp)",
+                "[tmux] /src/b  (1 window)",
+                "[hist] /src/a",
+                "[dir]  /src/c"
+            ]
+        );
+    }
+
+    #[test]
+    fn open_previews_get_the_entry_paths() {
+        let preview = label_preview("echo {}");
+        let run = |label: &str| {
+            let command = preview.replace("{}", &format!("'{}'", label));
+            let output = std::process::Command::new("sh").args(["-c", &command]).output().unwrap();
+            String::from_utf8(output.stdout).unwrap()
+        };
+
+        assert_eq!(run("[tmux] /src/my app  (1 window)"), "/src/my app\n");
+        assert_eq!(run("[hist] /src/a"), "/src/a\n");
+        assert_eq!(run("[dir]  /src/c"), "/src/c\n");
+    }
+
+    #[tokio::test]
+    async fn open_creates_sessions_for_directories() {
+        let dir = tempfile::tempdir().unwrap();
+        let (config, state, sessions) = setup(&dir, &["a"]);
+        let target = dir.path().join("b").to_string_lossy().to_string();
+        std::fs::create_dir(&target).unwrap();
+        let mux = Fake::with_sessions(&[&sessions[0]]);
+
+        let entry = Entry {
+            kind: Kind::Directory,
+            path: Some(target.clone()),
+            name: String::new(),
+            details: None,
+        };
+        enter(&mux, &config, state, &entry).await.unwrap();
+
+        assert_eq!(reload(&dir), vec![sessions[0].clone(), target.clone()]);
+        assert_eq!(mux.state().current, Some(target.clone()));
+    }
+
+    #[tokio::test]
+    async fn open_switches_to_foreign_sessions_by_name() {
+        let dir = tempfile::tempdir().unwrap();
+        let (config, state, sessions) = setup(&dir, &["a"]);
+        let mux = Fake::with_sessions(&["scratch", &sessions[0]]);
+
+        let entry =
+            Entry { kind: Kind::Running, path: None, name: "scratch".into(), details: None };
+        enter(&mux, &config, state, &entry).await.unwrap();
+
+        assert_eq!(reload(&dir), sessions);
+        assert_eq!(mux.state().current, Some("scratch".to_string()));
+    }
+}
@@ -0,0 +1,352 @@
+//! Snapshots of the running sessions, to recreate them after a reboot or a tmux server crash.
+//!
+//! A snapshot records the windows of every session sessionizer manages, with their tmux layout
+//! and the directory and program of each pane, as a [`Layout`] applied again on restore.
+
+use std::path::{Path, PathBuf};
+
+use clap::{Parser, Subcommand};
+use color_eyre::eyre::{bail, Result, WrapErr};
+use serde::{Deserialize, Serialize};
+
+use crate::config::Config;
+use crate::layout::{Layout, Pane, Window};
+use crate::state::{now, State};
+use crate::tmux::{Multiplexer, SessionOptions, TmuxPane, TmuxWindow};
+
+/// Snapshot saved and restored when no name is given.
+const DEFAULT: &str = "last";
+
+/// Programs panes run when idle, which are already started with every new pane.
+const SHELLS: [&str; 8] = ["sh", "bash", "zsh", "fish", "dash", "ksh", "tcsh", "nu"];
+
+#[derive(Debug, Subcommand)]
+pub enum Commands {
+    /// Save the windows and panes of every running sessionizer session
+    #[clap(name = "save")]
+    Save {
+        /// Name of the snapshot
+        #[clap(default_value = DEFAULT)]
+        name: String,
+    },
+    /// Recreate the sessions of a snapshot that aren't running
+    #[clap(name = "restore")]
+    Restore {
+        /// Name of the snapshot
+        #[clap(default_value = DEFAULT)]
+        name: String,
+    },
+    /// List the saved snapshots
+    #[clap(name = "list", alias = "ls")]
+    List,
+}
+
+#[derive(Debug, Parser)]
+#[command(name = "snapshot")]
+#[command(about = "Save and restore the layout of the running sessions")]
+pub struct Cli {
+    #[command(subcommand)]
+    pub command: Commands,
+}
+
+/// Sessions running when the snapshot was saved.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Snapshot {
+    /// Unix timestamp of the save, in seconds.
+    pub saved: u64,
+    pub sessions: Vec<Session>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Session {
+    /// Name of the tmux session.
+    pub name: String,
+    /// Directory the session is rooted at.
+    pub path: String,
+    /// Windows and panes of the session.
+    pub layout: Layout,
+}
+
+pub async fn run(mux: &impl Multiplexer, config: Config, state: State, cli: Cli) -> Result<()> {
+    let dir = dir(&state);
+    match cli.command {
+        Commands::Save { name } => {
+            let snapshot = save(mux, &config, &state).await?;
+            write(&dir, &name, &snapshot)?;
+            println!("Saved {} session(s) in snapshot {}", snapshot.sessions.len(), name);
+            Ok(())
+        }
+        Commands::Restore { name } => {
+            let snapshot = read(&dir, &name)?;
+            restore(mux, &config, state, &snapshot).await
+        }
+        Commands::List => list(&dir),
+    }
+}
+
+/// Captures the windows of the running sessions sessionizer manages.
+pub async fn save(mux: &impl Multiplexer, config: &Config, state: &State) -> Result<Snapshot> {
+    let mut sessions = Vec::new();
+    for session in mux.sessions().await? {
+        let Some(path) = crate::sessions::path_of(config, state, &session.name) else {
+            log::debug!("Skipping session {} not managed by sessionizer", session.name);
+            continue;
+        };
+        let windows = mux.windows(&session.name).await?;
+        sessions.push(Session { name: session.name, path, layout: layout(&windows) });
+    }
+
+    Ok(Snapshot { saved: now(), sessions })
+}
+
+/// Creates the sessions of `snapshot` that aren't running, with their saved windows.
+///
+/// Sessions whose directory doesn't exist anymore are skipped.
+pub async fn restore(
+    mux: &impl Multiplexer,
+    config: &Config,
+    mut state: State,
+    snapshot: &Snapshot,
+) -> Result<()> {
+    for session in &snapshot.sessions {
+        if mux.has_session(&session.name).await? {
+            println!("Session {} is already running", session.name);
+            continue;
+        }
+        if !Path::new(&session.path).is_dir() {
+            println!("Skipping session {}, {} doesn't exist anymore", session.name, session.path);
+            continue;
+        }
+
+        let options = SessionOptions {
+            layout: Some(session.layout.clone()),
+            ..crate::sessions::resolve(config, &session.path)?.options(config)
+        };
+        mux.create_session(&session.name, &session.path, &options).await?;
+
+        state.update(|state| {
+            state.names.entry(session.path.clone()).or_insert_with(|| session.name.clone());
+            if !state.history.contains(&session.path) {
+                state.history.push(session.path.clone());
+            }
+        })?;
+        println!("Restored session {}", session.name);
+    }
+
+    Ok(())
+}
+
+/// Prints the saved snapshots with the number of sessions in each.
+fn list(dir: &Path) -> Result<()> {
+    let mut names: Vec<String> = match std::fs::read_dir(dir) {
+        Ok(entries) => entries
+            .filter_map(|entry| entry.ok())
+            .filter_map(|entry| entry.file_name().to_str()?.strip_suffix(".json").map(String::from))
+            .collect(),
+        Err(_) => Vec::new(),
+    };
+    names.sort();
+
+    if names.is_empty() {
+        println!("No snapshot saved yet");
+    }
+
+    let now = now();
+    for name in names {
+        match read(dir, &name) {
+            Ok(snapshot) => println!(
+                "{}  {} session(s)  saved {}",
+                name,
+                snapshot.sessions.len(),
+                crate::sessions::ago(now.saturating_sub(snapshot.saved))
+            ),
+            Err(err) => log::warn!("Skipping snapshot {}: {:#}", name, err),
+        }
+    }
+
+    Ok(())
+}
+
+/// Converts the `windows` of a running session into a layout building them again.
+fn layout(windows: &[TmuxWindow]) -> Layout {
+    let windows = windows
+        .iter()
+        .map(|window| Window {
+            name: window.name.clone(),
+            cwd: None,
+            layout: Some(window.layout.clone()).filter(|layout| !layout.is_empty()),
+            panes: window
+                .panes
+                .iter()
+                .map(|pane| Pane {
+                    cwd: Some(pane.path.clone()),
+                    command: command(pane),
+                    ..Default::default()
+                })
+                .collect(),
+        })
+        .collect();
+
+    Layout { windows }
+}
+
+/// Returns the command line to start the program of `pane` again, `None` for shells.
+///
+/// Programs whose arguments couldn't be read are left out rather than started without them.
+fn command(pane: &TmuxPane) -> Option<String> {
+    if is_shell(&pane.command) {
+        return None;
+    }
+    if pane.args.is_none() {
+        log::debug!("Skipping {} in {}, its arguments are unknown", pane.command, pane.path);
+    }
+    pane.args.clone()
+}
+
+/// Checks if `command` is a shell, including the login shells tmux shows as `-zsh`.
+fn is_shell(command: &str) -> bool {
+    let command = command.trim_start_matches('-');
+    let user_shell = std::env::var("SHELL").ok();
+    let user_shell = user_shell.as_deref().and_then(|shell| Path::new(shell).file_name());
+
+    command.is_empty()
+        || SHELLS.contains(&command)
+        || user_shell.is_some_and(|shell| shell.to_string_lossy() == command)
+}
+
+/// Folder of the snapshots, next to the state file.
+fn dir(state: &State) -> PathBuf {
+    Path::new(state.path()).parent().unwrap_or(Path::new(".")).join("snapshots")
+}
+
+/// Returns the file of the snapshot called `name` in `dir`.
+fn file(dir: &Path, name: &str) -> Result<PathBuf> {
+    if name.is_empty() || name.starts_with('.') || name.contains(['/', '\\']) {
+        bail!("invalid snapshot name: {}", name);
+    }
+    Ok(dir.join(format!("{}.json", name)))
+}
+
+fn write(dir: &Path, name: &str, snapshot: &Snapshot) -> Result<()> {
+    let path = file(dir, name)?;
+    std::fs::create_dir_all(dir).wrap_err("fail to create the snapshot directory")?;
+
+    let text = serde_json::to_string_pretty(snapshot).wrap_err("fail to serialize snapshot")?;
+    crate::files::write_atomic(&path.to_string_lossy(), text.as_bytes())
+        .wrap_err("fail to save snapshot")
+}
+
+fn read(dir: &Path, name: &str) -> Result<Snapshot> {
+    let path = file(dir, name)?;
+    let text = std::fs::read_to_string(&path)
+        .wrap_err_with(|| format!("fail to read snapshot {}", name))?;
+    serde_json::from_str(&text).wrap_err_with(|| format!("fail to parse snapshot {}", name))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::layout::Split;
+    use crate::tmux::fake::{Fake, SHELL};
+
+    #[tokio::test]
+    async fn restore_recreates_the_saved_windows() {
+        let dir = tempfile::tempdir().unwrap();
+        let config = Config::new(&dir.path().join("sessionizer.yaml").to_string_lossy());
+        let root = dir.path().join("app");
+        std::fs::create_dir_all(root.join("api")).unwrap();
+        let root = root.to_string_lossy().to_string();
+        let api = format!("{}/api", root);
+        let mut state = State::new(&dir.path().join("state.json").to_string_lossy());
+        state.history = vec![root.clone()];
+        state.names.insert(root.clone(), "app".into());
+        state.save().unwrap();
+
+        let mux = Fake::with_sessions(&["scratch"]);
+        let layout = Layout {
+            windows: vec![
+                Window {
+                    name: Some("editor".into()),
+                    panes: vec![Pane {
+                        command: Some("nvim src/main.rs".into()),
+                        ..Default::default()
+                    }],
+                    ..Default::default()
+                },
+                Window {
+                    cwd: Some("api".into()),
+                    layout: Some("even-horizontal".into()),
+                    panes: vec![
+                        Pane::default(),
+                        Pane {
+                            split: Split::Horizontal,
+                            command: Some("cargo watch -x run".into()),
+                            ..Default::default()
+                        },
+                    ],
+                    ..Default::default()
+                },
+            ],
+        };
+        let options = SessionOptions { layout: Some(layout), ..Default::default() };
+        mux.create_session("app", &root, &options).await.unwrap();
+
+        let snapshot = save(&mux, &config, &state).await.unwrap();
+        assert_eq!(snapshot.sessions.len(), 1);
+        let saved = &snapshot.sessions[0];
+        assert_eq!((saved.name.as_str(), saved.path.as_str()), ("app", root.as_str()));
+        let panes = &saved.layout.windows[1].panes;
+        assert_eq!(panes[0].cwd.as_deref(), Some(api.as_str()));
+        assert_eq!(panes[0].command, None);
+        assert_eq!(panes[1].command.as_deref(), Some("cargo watch -x run"));
+
+        let restored = Fake::with_sessions(&[]);
+        restore(&restored, &config, state, &snapshot).await.unwrap();
+
+        let windows = restored.windows("app").await.unwrap();
+        assert_eq!(windows, mux.windows("app").await.unwrap());
+        assert_eq!(windows[1].panes[0].command, SHELL);
+        assert_eq!(windows[1].layout, "even-horizontal");
+        assert_eq!(windows[1].panes[1].args.as_deref(), Some("cargo watch -x run"));
+
+        restore(
+            &restored,
+            &config,
+            State::load(&dir.path().join("state.json").to_string_lossy()).unwrap(),
+            &snapshot,
+        )
+        .await
+        .unwrap();
+        assert_eq!(restored.state().sessions, vec!["app"]);
+    }
+
+    #[tokio::test]
+    async fn restore_skips_missing_directories() {
+        let dir = tempfile::tempdir().unwrap();
+        let config = Config::new(&dir.path().join("sessionizer.yaml").to_string_lossy());
+        let state = State::new(&dir.path().join("state.json").to_string_lossy());
+        let gone = dir.path().join("gone").to_string_lossy().to_string();
+        let snapshot = Snapshot {
+            saved: 0,
+            sessions: vec![Session { name: "gone".into(), path: gone, layout: Layout::default() }],
+        };
+
+        let mux = Fake::with_sessions(&[]);
+        restore(&mux, &config, state, &snapshot).await.unwrap();
+
+        assert!(mux.state().sessions.is_empty());
+    }
+
+    #[test]
+    fn snapshots_are_stored_by_name() {
+        let dir = tempfile::tempdir().unwrap();
+        let snapshot = Snapshot { saved: 42, sessions: Vec::new() };
+
+        write(dir.path(), "work", &snapshot).unwrap();
+
+        assert_eq!(read(dir.path(), "work").unwrap(), snapshot);
+        assert!(dir.path().join("work.json").exists());
+        assert!(write(dir.path(), "../escape", &snapshot).is_err());
+        assert!(read(dir.path(), ".hidden").is_err());
+    }
+}
@@ -0,0 +1,489 @@
+use std::collections::BTreeMap;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use color_eyre::eyre::{Result, WrapErr};
+use serde::{Deserialize, Serialize};
+
+use crate::config::Config;
+use crate::files::{self, Lock};
+
+/// Total rank above which every visit count is aged, like zoxide's `_ZO_MAXAGE`.
+const MAX_RANK: f64 = 10_000.0;
+
+const HOUR: u64 = 60 * 60;
+const DAY: u64 = 24 * HOUR;
+const WEEK: u64 = 7 * DAY;
+
+/// How often and how recently a path was visited.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+pub struct Visits {
+    /// Visit count, decayed over time by aging.
+    pub rank: f64,
+    /// Unix timestamp of the last visit, in seconds.
+    pub last: u64,
+}
+
+impl Visits {
+    /// Scores the visits at `now`, favoring recent ones the way zoxide does.
+    pub fn frecency(&self, now: u64) -> f64 {
+        let age = now.saturating_sub(self.last);
+        let factor = if age < HOUR {
+            4.0
+        } else if age < DAY {
+            2.0
+        } else if age < WEEK {
+            0.5
+        } else {
+            0.25
+        };
+
+        self.rank * factor
+    }
+}
+
+/// Limits on the history, applied by `sessions prune` and, with `prune`, every time the state
+/// is loaded.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct Policy {
+    /// Prune the history every time the state is loaded.
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub prune: bool,
+    /// Number of entries kept, the most recent ones.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_length: Option<usize>,
+    /// Days an entry is kept after its last visit.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_age: Option<u64>,
+}
+
+impl Policy {
+    pub fn is_empty(&self) -> bool {
+        *self == Self::default()
+    }
+}
+
+/// Why a history entry is pruned.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Reason {
+    /// The directory was deleted or moved.
+    Missing,
+    /// The entry wasn't visited for longer than the `max_age` of the policy.
+    Expired,
+    /// The entry is older than the `max_length` most recent ones.
+    Overflow,
+}
+
+impl std::fmt::Display for Reason {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let label = match self {
+            Reason::Missing => "missing",
+            Reason::Expired => "expired",
+            Reason::Overflow => "overflow",
+        };
+        f.pad(label)
+    }
+}
+
+/// Returns the current Unix timestamp, in seconds.
+pub fn now() -> u64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_secs()).unwrap_or_default()
+}
+
+/// Runtime data sessionizer keeps between invocations.
+///
+/// It lives apart from the user's configuration so that moving between sessions never rewrites
+/// the hand-edited configuration file.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct State {
+    /// Visited sessions, the current one being the last.
+    #[serde(default)]
+    pub history: Vec<String>,
+    /// Visits of every session path, used to rank the pickers.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub visits: BTreeMap<String, Visits>,
+    /// Tmux session name of every path a session was created for.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub names: BTreeMap<String, String>,
+    #[serde(skip)]
+    path: String,
+}
+
+impl State {
+    pub fn new(path: &str) -> Self {
+        Self {
+            history: vec![],
+            visits: BTreeMap::new(),
+            names: BTreeMap::new(),
+            path: path.to_string(),
+        }
+    }
+
+    /// Path of the state file.
+    pub fn path(&self) -> &str {
+        &self.path
+    }
+
+    pub fn save(&self) -> Result<()> {
+        if let Some(parent) = std::path::Path::new(&self.path).parent() {
+            std::fs::create_dir_all(parent).wrap_err("fail to create the state directory")?;
+        }
+
+        let text = serde_json::to_string_pretty(&self).wrap_err("fail to serialize state")?;
+
+        files::write_atomic(&self.path, text.as_bytes()).wrap_err("fail to save state")?;
+        Ok(())
+    }
+
+    /// Applies `f` to the latest state on disk and saves the result while holding the state lock.
+    ///
+    /// The state is reloaded right before `f` runs, so concurrent invocations never overwrite
+    /// each other's changes with stale data.
+    pub fn update<T>(&mut self, f: impl FnOnce(&mut Self) -> T) -> Result<T> {
+        let _lock = Lock::acquire(&self.path)?;
+
+        *self = Self::load(&self.path)?;
+        let result = f(self);
+        self.save()?;
+
+        Ok(result)
+    }
+
+    /// Moves `session` to the end of the history, making it the current one, and records the
+    /// visit.
+    pub fn visit(&mut self, session: &str) {
+        self.history.retain(|s| s != session);
+        self.history.push(session.to_string());
+        self.record(session, now());
+    }
+
+    fn record(&mut self, session: &str, now: u64) {
+        let visits = self.visits.entry(session.to_string()).or_default();
+        visits.rank += 1.0;
+        visits.last = now;
+
+        // Age the ranks once they grow too large, forgetting the paths that fall below one visit.
+        let total: f64 = self.visits.values().map(|v| v.rank).sum();
+        if total > MAX_RANK {
+            let factor = 0.9 * MAX_RANK / total;
+            self.visits.retain(|_, visits| {
+                visits.rank *= factor;
+                visits.rank >= 1.0
+            });
+        }
+    }
+
+    /// Returns the session name of `path`, giving it `preferred`, or a disambiguated variant of
+    /// it, if it has none yet.
+    ///
+    /// Names of other sessions, assigned or in the `running` ones, are never reused.
+    pub fn assign(&mut self, path: &str, preferred: &str, running: &[String]) -> String {
+        if let Some(name) = self.names.get(path) {
+            return name.clone();
+        }
+
+        let name = crate::naming::disambiguate(preferred, path, |name| {
+            self.names.values().chain(running).any(|taken| taken == name)
+        });
+        self.names.insert(path.to_string(), name.clone());
+        name
+    }
+
+    /// Returns the path whose session is named `name`.
+    pub fn path_of(&self, name: &str) -> Option<&str> {
+        self.names.iter().find(|(_, n)| *n == name).map(|(path, _)| path.as_str())
+    }
+
+    /// Returns the history entries `policy` prunes at `now`, oldest first.
+    ///
+    /// Entries whose directory is gone are always pruned. The current session is never pruned
+    /// for its age or the length of the history.
+    pub fn stale(&self, policy: &Policy, now: u64) -> Vec<(String, Reason)> {
+        let current = self.history.len().saturating_sub(1);
+        let expired = |path: &String| {
+            let last = self.visits.get(path).map_or(0, |visits| visits.last);
+            policy.max_age.is_some_and(|days| last > 0 && now.saturating_sub(last) > days * DAY)
+        };
+
+        let mut stale = Vec::new();
+        let mut kept = Vec::new();
+        for (index, path) in self.history.iter().enumerate() {
+            if !std::path::Path::new(path).is_dir() {
+                stale.push((index, Reason::Missing));
+            } else if index != current && expired(path) {
+                stale.push((index, Reason::Expired));
+            } else {
+                kept.push(index);
+            }
+        }
+
+        // The current session comes last, so it's always among the most recent ones.
+        let overflow = kept.len().saturating_sub(policy.max_length.unwrap_or(usize::MAX));
+        stale.extend(kept[..overflow].iter().map(|index| (*index, Reason::Overflow)));
+        stale.sort_by_key(|(index, _)| *index);
+
+        stale.into_iter().map(|(index, reason)| (self.history[index].clone(), reason)).collect()
+    }
+
+    /// Removes the [`stale`](Self::stale) entries from the history and returns them.
+    ///
+    /// Missing directories are forgotten altogether, with their visits and session names.
+    pub fn prune(&mut self, policy: &Policy, now: u64) -> Vec<(String, Reason)> {
+        let stale = self.stale(policy, now);
+        self.history.retain(|path| !stale.iter().any(|(stale, _)| stale == path));
+        for (path, reason) in &stale {
+            if *reason == Reason::Missing {
+                self.visits.remove(path);
+                self.names.remove(path);
+            }
+        }
+
+        stale
+    }
+
+    /// Returns `items` ordered by decreasing frecency, keeping the given order between ties.
+    pub fn ranked<'a>(&self, items: impl IntoIterator<Item = &'a String>) -> Vec<String> {
+        let now = now();
+        let score = |item: &String| self.visits.get(item).map_or(0.0, |v| v.frecency(now));
+
+        let mut items: Vec<String> = items.into_iter().cloned().collect();
+        items.sort_by(|a, b| score(b).total_cmp(&score(a)));
+        items
+    }
+
+    /// Loads the state file, or returns an empty state if it doesn't exist yet.
+    pub fn load(path: &str) -> Result<Self> {
+        if !std::path::Path::new(path).exists() {
+            return Ok(Self::new(path));
+        }
+        let json = std::fs::read_to_string(path)?;
+        let mut state: Self = serde_json::from_str(&json).wrap_err("fail to deserialize state")?;
+        log::debug!("state = {:#?}", state);
+        state.path = path.to_string();
+        Ok(state)
+    }
+
+    /// Loads the state file, creating it from the legacy `sessions` key of `config` when it
+    /// doesn't exist yet.
+    ///
+    /// The configuration file is never rewritten: once the state file exists, the legacy key is
+    /// ignored. The history is pruned along the way when the `history` policy of `config` asks
+    /// for it.
+    pub fn load_or_migrate(path: &str, config: &Config) -> Result<Self> {
+        if std::path::Path::new(path).exists() || config.sessions.is_empty() {
+            if !config.sessions.is_empty() {
+                log::debug!("Ignoring the configuration sessions, {} holds the history", path);
+            }
+            let mut state = Self::load(path)?;
+            if config.history.prune && !state.stale(&config.history, now()).is_empty() {
+                let pruned = state.update(|state| state.prune(&config.history, now()))?;
+                log::debug!("Pruned {:?} from the history", pruned);
+            }
+            return Ok(state);
+        }
+
+        log::debug!("Migrating the configuration sessions to {}", path);
+        let mut state = Self::new(path);
+        state.update(|state| {
+            if state.history.is_empty() {
+                state.history = config.sessions.clone();
+            }
+        })?;
+        eprintln!(
+            "Moved the session history to {}, the `sessions` key of {} can be removed.",
+            path,
+            config.path()
+        );
+
+        Ok(state)
+    }
+
+    /// Default state file path, inside `$XDG_STATE_HOME/sessionizer`.
+    pub fn home() -> Result<String> {
+        let dir = match std::env::var("XDG_STATE_HOME") {
+            Ok(dir) if !dir.is_empty() => dir,
+            _ => format!("{}/.local/state", std::env::var("HOME")?),
+        };
+
+        Ok(format!("{}/sessionizer/state.json", dir))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn missing_state_files_load_empty() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("state.json").to_string_lossy().to_string();
+
+        assert!(State::load(&path).unwrap().history.is_empty());
+    }
+
+    #[test]
+    fn update_applies_changes_over_the_latest_state() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("state.json").to_string_lossy().to_string();
+        let mut state = State::new(&path);
+        state.history = vec!["/a".into(), "/b".into()];
+        state.save().unwrap();
+
+        let mut stale = State::load(&path).unwrap();
+        State::load(&path).unwrap().update(|s| s.history.retain(|h| h != "/a")).unwrap();
+        stale.update(|s| s.history.push("/c".into())).unwrap();
+
+        assert_eq!(State::load(&path).unwrap().history, vec!["/b", "/c"]);
+        assert_eq!(stale.history, vec!["/b", "/c"]);
+    }
+
+    #[test]
+    fn concurrent_updates_are_not_lost() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("state.json").to_string_lossy().to_string();
+
+        std::thread::scope(|scope| {
+            for i in 0..16 {
+                let path = &path;
+                scope.spawn(move || {
+                    State::new(path).update(|s| s.history.push(i.to_string())).unwrap();
+                });
+            }
+        });
+
+        assert_eq!(State::load(&path).unwrap().history.len(), 16);
+    }
+
+    #[test]
+    fn recent_visits_outrank_older_frequent_ones() {
+        let now = 100 * WEEK;
+        let mut state = State::new("state.json");
+        for _ in 0..5 {
+            state.record("/old", now - 2 * WEEK);
+        }
+        state.record("/daily", now - 2 * HOUR);
+        state.record("/daily", now - HOUR);
+        state.record("/new", now);
+
+        let score = |path: &str| state.visits[path].frecency(now);
+        assert_eq!(score("/old"), 1.25);
+        assert_eq!(score("/daily"), 4.0);
+        assert_eq!(score("/new"), 4.0);
+        assert!(score("/daily") > score("/old"));
+    }
+
+    #[test]
+    fn ranked_keeps_the_order_of_unvisited_items() {
+        let mut state = State::new("state.json");
+        state.visit("/c");
+        let items = vec!["/a".to_string(), "/b".to_string(), "/c".to_string()];
+
+        assert_eq!(state.ranked(&items), vec!["/c", "/a", "/b"]);
+    }
+
+    #[test]
+    fn aging_forgets_rarely_visited_paths() {
+        let mut state = State::new("state.json");
+        state.record("/rare", 0);
+        for _ in 0..MAX_RANK as usize {
+            state.record("/often", 0);
+        }
+
+        assert!(!state.visits.contains_key("/rare"));
+        assert!(state.visits["/often"].rank < MAX_RANK);
+    }
+
+    #[test]
+    fn assigned_names_are_unique_and_stable() {
+        let mut state = State::new("state.json");
+
+        assert_eq!(state.assign("/src/work/api", "api", &[]), "api");
+        assert_eq!(state.assign("/src/home/api", "api", &[]), "home/api");
+        assert_eq!(state.assign("/src/work/api", "other", &[]), "api");
+        assert_eq!(state.assign("/src/web", "web", &["web".into()]), "src/web");
+        assert_eq!(state.path_of("home/api"), Some("/src/home/api"));
+        assert_eq!(state.path_of("web"), None);
+    }
+
+    #[test]
+    fn stale_entries_are_missing_expired_or_overflowing() {
+        let dir = tempfile::tempdir().unwrap();
+        let paths: Vec<String> = ["a", "b", "c", "d", "e"]
+            .iter()
+            .map(|name| dir.path().join(name).to_string_lossy().to_string())
+            .collect();
+        for path in &paths[1..] {
+            std::fs::create_dir(path).unwrap();
+        }
+        let now = 100 * WEEK;
+        let mut state = State::new("state.json");
+        state.history = paths.clone();
+        state.record(&paths[0], now);
+        state.record(&paths[1], now - 40 * DAY);
+        state.record(&paths[4], now - 40 * DAY);
+        state.names.insert(paths[0].clone(), "a".into());
+        let policy = Policy { max_length: Some(1), max_age: Some(30), ..Default::default() };
+
+        assert_eq!(state.stale(&Policy::default(), now), vec![(paths[0].clone(), Reason::Missing)]);
+        assert_eq!(
+            state.prune(&policy, now),
+            vec![
+                (paths[0].clone(), Reason::Missing),
+                (paths[1].clone(), Reason::Expired),
+                (paths[2].clone(), Reason::Overflow),
+                (paths[3].clone(), Reason::Overflow),
+            ]
+        );
+        assert_eq!(state.history, vec![paths[4].clone()]);
+        assert!(!state.visits.contains_key(&paths[0]));
+        assert!(state.visits.contains_key(&paths[1]));
+        assert!(state.names.is_empty());
+    }
+
+    #[test]
+    fn history_is_pruned_on_load_when_configured() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("state.json").to_string_lossy().to_string();
+        let mut state = State::new(&path);
+        state.history = vec![dir.path().join("gone").to_string_lossy().to_string()];
+        state.save().unwrap();
+        let mut config = Config::new("sessionizer.yaml");
+
+        assert_eq!(State::load_or_migrate(&path, &config).unwrap().history.len(), 1);
+        config.history.prune = true;
+        assert!(State::load_or_migrate(&path, &config).unwrap().history.is_empty());
+        assert!(State::load(&path).unwrap().history.is_empty());
+    }
+
+    #[test]
+    fn legacy_sessions_are_migrated_once() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("sessionizer/state.json").to_string_lossy().to_string();
+        let mut config = Config::new("sessionizer.yaml");
+        config.sessions = vec!["/a".into(), "/b".into()];
+
+        let state = State::load_or_migrate(&path, &config).unwrap();
+        assert_eq!(state.history, vec!["/a", "/b"]);
+        assert_eq!(State::load(&path).unwrap().history, vec!["/a", "/b"]);
+
+        config.sessions = vec!["/c".into()];
+        assert_eq!(State::load_or_migrate(&path, &config).unwrap().history, vec!["/a", "/b"]);
+    }
+
+    #[test]
+    fn legacy_sessions_are_migrated_without_rewriting_the_config() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("state.json").to_string_lossy().to_string();
+        let config_path = dir.path().join("sessionizer.yaml").to_string_lossy().to_string();
+        let mut config = Config::new(&config_path);
+        config.sessions = vec!["/a".into()];
+        config.save().unwrap();
+
+        config.update(|config| config.env.push("KEY=value".into())).unwrap();
+        assert_eq!(config.sessions, vec!["/a"]);
+
+        let written = std::fs::read_to_string(&config_path).unwrap();
+        let state = State::load_or_migrate(&path, &config).unwrap();
+        assert_eq!(state.history, vec!["/a"]);
+        assert_eq!(std::fs::read_to_string(&config_path).unwrap(), written);
+    }
+}
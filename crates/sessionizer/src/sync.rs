@@ -0,0 +1,315 @@
+//! Plans of `sessions sync`, telling what happens to every session before anything is done.
+
+use std::fmt;
+
+use color_eyre::eyre::{Result, WrapErr};
+use globset::{Glob, GlobSet, GlobSetBuilder};
+use serde::{Deserialize, Serialize};
+
+use crate::config::Config;
+use crate::state::State;
+use crate::tmux::TmuxSession;
+
+/// Settings of `sessions sync`.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct Options {
+    /// Globs of session names or paths sync never creates, kills or adds to the history, e.g.
+    /// `scratch*`, `~` being expanded.
+    #[serde(default, alias = "ignore", skip_serializing_if = "Vec::is_empty")]
+    pub protect: Vec<String>,
+}
+
+impl Options {
+    pub fn is_empty(&self) -> bool {
+        *self == Self::default()
+    }
+
+    fn protected(&self) -> Result<GlobSet> {
+        let mut builder = GlobSetBuilder::new();
+        for pattern in &self.protect {
+            let glob = Glob::new(&shellexpand::tilde(pattern))
+                .wrap_err_with(|| format!("invalid protect pattern {}", pattern))?;
+            builder.add(glob);
+        }
+
+        builder.build().wrap_err("fail to build the protect patterns")
+    }
+}
+
+/// What sync does to a session.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Action {
+    /// Create the tmux session of a history entry.
+    Create,
+    /// Kill a tmux session missing from the history.
+    Kill,
+    /// Add a running tmux session to the history.
+    Add,
+    /// Add a tmux session created by other means to the history, under its directory.
+    Adopt,
+    /// Remove a history entry without a running tmux session.
+    Drop,
+    /// Leave a session that is both running and in the history as it is.
+    Keep,
+    /// Leave a session matching a `protect` pattern as it is.
+    Protect,
+    /// Leave a tmux session without a directory of its own out of the history.
+    Skip,
+}
+
+impl Action {
+    /// Checks if the action changes anything.
+    pub fn changes(self) -> bool {
+        !matches!(self, Action::Keep | Action::Protect | Action::Skip)
+    }
+}
+
+impl fmt::Display for Action {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let label = match self {
+            Action::Create => "create",
+            Action::Kill => "kill",
+            Action::Add => "add",
+            Action::Adopt => "adopt",
+            Action::Drop => "drop",
+            Action::Keep => "keep",
+            Action::Protect => "protect",
+            Action::Skip => "skip",
+        };
+        f.pad(label)
+    }
+}
+
+/// Action planned for one session.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Step {
+    pub action: Action,
+    /// Name of the tmux session.
+    pub name: String,
+    /// Directory of the session, when sessionizer knows it.
+    pub path: Option<String>,
+}
+
+impl fmt::Display for Step {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:<8}{}", self.action, self.name)?;
+        match &self.path {
+            Some(path) if *path != self.name => write!(f, "  ({})", path),
+            _ => Ok(()),
+        }
+    }
+}
+
+/// Plans syncing tmux to the history: the sessions of the history are created, and the other
+/// `running` sessions killed.
+pub fn to_tmux(config: &Config, state: &State, running: &[TmuxSession]) -> Result<Vec<Step>> {
+    let protected = config.sync.protected()?;
+    let is_protected = |name: &str, path: Option<&str>| {
+        protected.is_match(name) || path.is_some_and(|path| protected.is_match(path))
+    };
+
+    let mut steps = Vec::new();
+    for path in &state.history {
+        let name = crate::sessions::name_of(config, state, path);
+        let action = if is_protected(&name, Some(path)) {
+            Action::Protect
+        } else if running.iter().any(|session| session.name == name) {
+            Action::Keep
+        } else {
+            Action::Create
+        };
+        steps.push(Step { action, name, path: Some(path.clone()) });
+    }
+
+    for session in running {
+        if steps.iter().any(|step| step.name == session.name) {
+            continue;
+        }
+        let path =
+            session.source.clone().or_else(|| state.path_of(&session.name).map(String::from));
+        let action = if is_protected(&session.name, path.as_deref()) {
+            Action::Protect
+        } else {
+            Action::Kill
+        };
+        steps.push(Step { action, name: session.name.clone(), path });
+    }
+
+    Ok(steps)
+}
+
+/// Plans syncing the history from tmux: the `running` sessions replace the history.
+///
+/// The directory of a session is the one sessionizer tagged it with, or the one its name belongs
+/// to. Sessions created by other means are adopted under their `session_path` when it's a
+/// directory without a session yet, and skipped otherwise. Protected entries stay first in the
+/// history, and protected sessions aren't added to it.
+pub fn from_tmux(config: &Config, state: &State, running: &[TmuxSession]) -> Result<Vec<Step>> {
+    let protected = config.sync.protected()?;
+    let is_protected =
+        |name: &str, path: &str| protected.is_match(name) || protected.is_match(path);
+
+    let mut steps = Vec::new();
+    for path in &state.history {
+        let name = crate::sessions::name_of(config, state, path);
+        if is_protected(&name, path) {
+            steps.push(Step { action: Action::Protect, name, path: Some(path.clone()) });
+        }
+    }
+
+    for session in running {
+        let name = session.name.clone();
+        let claimed =
+            |path: &str| steps.iter().any(|step: &Step| step.path.as_deref() == Some(path));
+        let known =
+            session.source.clone().or_else(|| crate::sessions::path_of(config, state, &name));
+
+        let step = match known {
+            Some(path) if claimed(&path) => continue,
+            Some(path) => {
+                let action = if is_protected(&name, &path) {
+                    Action::Protect
+                } else if state.history.contains(&path) {
+                    Action::Keep
+                } else {
+                    Action::Add
+                };
+                Step { action, name, path: Some(path) }
+            }
+            None if is_protected(&name, &session.path) => {
+                Step { action: Action::Protect, name, path: None }
+            }
+            None if std::path::Path::new(&session.path).is_dir()
+                && !claimed(&session.path)
+                && !state.names.contains_key(&session.path) =>
+            {
+                Step { action: Action::Adopt, name, path: Some(session.path.clone()) }
+            }
+            None => Step { action: Action::Skip, name, path: None },
+        };
+        steps.push(step);
+    }
+
+    for path in &state.history {
+        if !steps.iter().any(|step| step.path.as_ref() == Some(path)) {
+            let name = crate::sessions::name_of(config, state, path);
+            steps.push(Step { action: Action::Drop, name, path: Some(path.clone()) });
+        }
+    }
+
+    Ok(steps)
+}
+
+/// Returns the history left by applying the `steps` of [`from_tmux`] to `history`.
+pub fn history(steps: &[Step], history: &[String]) -> Vec<String> {
+    steps
+        .iter()
+        .filter(|step| match step.action {
+            Action::Add | Action::Adopt => true,
+            Action::Drop => false,
+            _ => step.path.as_ref().is_some_and(|path| history.contains(path)),
+        })
+        .filter_map(|step| step.path.clone())
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn setup() -> (Config, State) {
+        let mut config = Config::new("sessionizer.yaml");
+        config.sync.protect = vec!["scratch*".into(), "/src/keep".into()];
+        let mut state = State::new("state.json");
+        state.history = vec!["/src/a".into(), "/src/keep".into(), "/src/b".into()];
+        state.names = [("/src/a".to_string(), "a".to_string())].into();
+        (config, state)
+    }
+
+    fn running(names: &[&str]) -> Vec<TmuxSession> {
+        names
+            .iter()
+            .map(|name| TmuxSession { name: name.to_string(), ..Default::default() })
+            .collect()
+    }
+
+    fn actions(steps: &[Step]) -> Vec<(Action, &str)> {
+        steps.iter().map(|step| (step.action, step.name.as_str())).collect()
+    }
+
+    #[test]
+    fn to_tmux_never_touches_protected_sessions() {
+        let (config, state) = setup();
+        let running = running(&["a", "stray", "scratch-1"]);
+
+        let steps = to_tmux(&config, &state, &running).unwrap();
+
+        assert_eq!(
+            actions(&steps),
+            vec![
+                (Action::Keep, "a"),
+                (Action::Protect, "/src/keep"),
+                (Action::Create, "/src/b"),
+                (Action::Kill, "stray"),
+                (Action::Protect, "scratch-1"),
+            ]
+        );
+        assert_eq!(steps[0].to_string(), "keep    a  (/src/a)");
+        assert_eq!(steps[3].to_string(), "kill    stray");
+    }
+
+    #[test]
+    fn from_tmux_keeps_protected_entries() {
+        let (config, state) = setup();
+        let mut running = running(&["x", "a", "scratch-1"]);
+        running[0].source = Some("/src/x".into());
+
+        let steps = from_tmux(&config, &state, &running).unwrap();
+
+        assert_eq!(
+            actions(&steps),
+            vec![
+                (Action::Protect, "/src/keep"),
+                (Action::Add, "x"),
+                (Action::Keep, "a"),
+                (Action::Protect, "scratch-1"),
+                (Action::Drop, "/src/b"),
+            ]
+        );
+        assert_eq!(history(&steps, &state.history), vec!["/src/keep", "/src/x", "/src/a"]);
+    }
+
+    #[test]
+    fn from_tmux_adopts_foreign_sessions_with_a_directory() {
+        let dir = tempfile::tempdir().unwrap();
+        let home = dir.path().to_string_lossy().to_string();
+        let (config, state) = setup();
+        let mut running = running(&["main", "other", "x·y", "/src/b"]);
+        running[0].path = home.clone();
+        running[1].path = home.clone();
+        running[2].path = dir.path().join("gone").to_string_lossy().to_string();
+
+        let steps = from_tmux(&config, &state, &running).unwrap();
+
+        assert_eq!(
+            actions(&steps),
+            vec![
+                (Action::Protect, "/src/keep"),
+                (Action::Adopt, "main"),
+                (Action::Skip, "other"),
+                (Action::Skip, "x·y"),
+                (Action::Keep, "/src/b"),
+                (Action::Drop, "a"),
+            ]
+        );
+        assert_eq!(history(&steps, &state.history), vec!["/src/keep", &home, "/src/b"]);
+    }
+
+    #[test]
+    fn invalid_patterns_are_reported() {
+        let (mut config, state) = setup();
+        config.sync.protect = vec!["[".into()];
+
+        assert!(to_tmux(&config, &state, &[]).is_err());
+    }
+}
@@ -1,265 +1,550 @@
 use color_eyre::eyre::{eyre, Result, WrapErr};
 
-pub async fn kill_session(session: &str) -> Result<()> {
-    log::debug!("$ tmux kill-session -t {}", session);
-    match tokio::process::Command::new("tmux")
-        .args(["kill-session", "-t", session])
-        .stdout(std::process::Stdio::piped())
-        .stderr(std::process::Stdio::piped())
-        .spawn()
-        .wrap_err("fail to spawn tmux")?
-        .wait_with_output()
-        .await
-    {
-        Ok(output) => {
-            if !output.status.success() {
-                log::error!("tmux kill-session failed with status: {}", output.status);
-                Err(eyre!(
-                    "tmux kill-session failed with status: {}\n{}",
-                    output.status,
-                    String::from_utf8(output.stderr)?
-                ))
-            } else {
-                log::debug!(
-                    "tmux kill-session exited successfully\n{}",
-                    String::from_utf8(output.stdout)?
-                );
-                Ok(())
-            }
+use crate::layout::{Layout, Split};
+
+#[cfg(test)]
+pub mod fake;
+mod name;
+
+/// Operations sessionizer needs from a terminal multiplexer.
+///
+/// The real implementation is [`Tmux`], which shells out to the `tmux` binary. Tests use the
+/// in-memory [`fake::Fake`] so the `sessions` module can be exercised without a tmux server.
+pub trait Multiplexer {
+    /// Kills the session with the given name.
+    async fn kill_session(&self, session: &str) -> Result<()>;
+    /// Switches the current client to the given session.
+    async fn switch_client(&self, session: &str) -> Result<()>;
+    /// Lists the running sessions.
+    async fn sessions(&self) -> Result<Vec<TmuxSession>>;
+    /// Returns the name of the session the current client is attached to, if the process runs
+    /// inside a client.
+    async fn current_session(&self) -> Result<Option<String>>;
+    /// Checks if there's a running server we are attached to.
+    async fn is_active(&self) -> Result<bool>;
+    /// Attaches the terminal to the given session.
+    async fn attach(&self, session: &str) -> Result<bool>;
+    /// Creates a new detached session rooted at the `cwd` directory and returns the id of its
+    /// initial pane.
+    async fn new_session(
+        &self,
+        session: &str,
+        cwd: &str,
+        options: &SessionOptions,
+    ) -> Result<String>;
+    /// Checks if a session exists.
+    async fn has_session(&self, session: &str) -> Result<bool>;
+    /// Appends a window to `session` without selecting it and returns the id of its pane.
+    async fn new_window(&self, session: &str, name: Option<&str>, cwd: &str) -> Result<String>;
+    /// Renames the window containing `target`.
+    async fn rename_window(&self, target: &str, name: &str) -> Result<()>;
+    /// Splits the `target` pane and returns the id of the new pane.
+    async fn split_window(&self, target: &str, split: Split, cwd: &str) -> Result<String>;
+    /// Types `keys` into the `target` pane followed by `Enter`.
+    async fn send_keys(&self, target: &str, keys: &str) -> Result<()>;
+    /// Arranges the panes of the window containing `target` using a tmux layout.
+    async fn select_layout(&self, target: &str, layout: &str) -> Result<()>;
+    /// Lists the windows of `session` with their panes.
+    async fn windows(&self, session: &str) -> Result<Vec<TmuxWindow>>;
+
+    /// Creates a new session and builds its layout, if any.
+    async fn create_session(
+        &self,
+        session: &str,
+        cwd: &str,
+        options: &SessionOptions,
+    ) -> Result<()> {
+        let pane = self.new_session(session, cwd, options).await?;
+
+        if let Some(layout) = &options.layout {
+            crate::layout::apply(self, session, cwd, &pane, layout).await?;
         }
-        Err(err) => Err(eyre!("fail to kill tmux session {}: {}", session, err)),
+
+        Ok(())
     }
-}
 
-pub async fn switch_client(session: &str) -> Result<()> {
-    let session = session.replace('.', "·");
-
-    log::debug!("$ tmux switch-client -t {}", session);
-    match tokio::process::Command::new("tmux")
-        .args(["switch-client", "-t", &session])
-        .stdout(std::process::Stdio::piped())
-        .stderr(std::process::Stdio::piped())
-        .spawn()
-        .wrap_err("fail to spawn tmux")?
-        .wait_with_output()
-        .await
-    {
-        Ok(output) => {
-            if !output.status.success() {
-                log::error!("tmux switch-client failed with status: {}", output.status);
-                Err(eyre!(
-                    "tmux switch-client failed with status: {}\n{}",
-                    output.status,
-                    String::from_utf8(output.stderr)?
-                ))
-            } else {
-                log::debug!(
-                    "tmux switch-client exited successfully\n{}",
-                    String::from_utf8(output.stdout)?
-                );
-                Ok(())
-            }
+    /// Creates the session with the `options` built on demand if needed, and moves the client to
+    /// it.
+    ///
+    /// Returns whether the session had to be created.
+    async fn set(
+        &self,
+        session: &str,
+        cwd: &str,
+        options: impl FnOnce() -> SessionOptions,
+    ) -> Result<bool> {
+        let created = !self.has_session(session).await?;
+        if created {
+            self.create_session(session, cwd, &options()).await?
         }
-        Err(err) => Err(eyre!("fail to switch client to session {}: {}", session, err)),
+
+        if self.is_active().await? {
+            self.switch_client(session).await?;
+        } else {
+            self.attach(session).await?;
+        }
+
+        Ok(created)
     }
 }
 
-pub async fn ls() -> Result<Vec<String>> {
-    log::debug!("$ tmux ls");
-    match tokio::process::Command::new("tmux")
-        .args(["ls"])
-        .stdout(std::process::Stdio::piped())
-        .stderr(std::process::Stdio::piped())
-        .spawn()
-        .wrap_err("fail to spawn tmux")?
-        .wait_with_output()
-        .await
-    {
-        Ok(output) => {
-            if !output.status.success() {
-                log::error!("tmux ls failed with status: {}", output.status);
-                Err(eyre!(
-                    "tmux ls failed with status: {}\n{}",
-                    output.status,
-                    String::from_utf8(output.stderr)?
-                ))
-            } else {
-                let stdout = String::from_utf8(output.stdout)
-                    .wrap_err("fail to get the output from stdout")?;
-
-                let sessions: Vec<String> = stdout
-                    .split('\n')
-                    .filter(|s| !s.is_empty())
-                    .map(|s| s.split(':').next().unwrap().to_string())
-                    .collect();
-
-                Ok(sessions)
-            }
-        }
-        Err(err) => Err(eyre!("fail to get the current tmux session: {}", err)),
+/// Settings applied to a session when it gets created.
+#[derive(Debug, Default, Clone)]
+pub struct SessionOptions {
+    /// `KEY=value` pairs set in the session environment.
+    pub env: Vec<String>,
+    /// Windows and panes built after the session is created.
+    pub layout: Option<Layout>,
+}
+
+/// Running tmux session.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct TmuxSession {
+    pub name: String,
+    /// Directory new windows start in.
+    pub path: String,
+    /// Number of clients attached to the session.
+    pub attached: usize,
+    pub windows: usize,
+    /// Unix timestamp of the last activity, in seconds.
+    pub activity: u64,
+    /// Unix timestamp of the creation, in seconds.
+    pub created: u64,
+    /// Directory sessionizer created the session for, `None` for sessions created by other means.
+    pub source: Option<String>,
+}
+
+/// User option recording the directory a session was created for, encoded like the names.
+const PATH_OPTION: &str = "@sessionizer_path";
+
+/// Format listing the fields of a [`TmuxSession`].
+///
+/// Fields are separated by tabs, which encoded names and options never contain, and the path
+/// comes last so it can contain tabs too.
+const SESSION_FORMAT: &str = "#{session_name}\t#{session_attached}\t#{session_windows}\t\
+    #{session_activity}\t#{session_created}\t#{@sessionizer_path}\t#{session_path}";
+
+impl TmuxSession {
+    /// Parses a line printed with [`SESSION_FORMAT`].
+    fn parse(line: &str) -> Result<Self> {
+        let mut fields = line.splitn(7, '\t');
+        let mut next = || fields.next().ok_or_else(|| eyre!("missing session fields: {}", line));
+
+        Ok(Self {
+            name: name::decode(next()?),
+            attached: next()?.parse().wrap_err("fail to parse the attached clients")?,
+            windows: next()?.parse().wrap_err("fail to parse the window count")?,
+            activity: next()?.parse().wrap_err("fail to parse the session activity")?,
+            created: next()?.parse().wrap_err("fail to parse the session creation")?,
+            source: Some(next()?).filter(|source| !source.is_empty()).map(name::decode),
+            path: next()?.to_string(),
+        })
     }
 }
 
-pub async fn current_session() -> Result<String> {
-    log::debug!("$ tmux display-message -p '#S'");
-    match tokio::process::Command::new("tmux")
-        .args(["display-message", "-p", "'#S'"])
-        .stdout(std::process::Stdio::piped())
-        .stderr(std::process::Stdio::piped())
-        .spawn()
-        .wrap_err("fail to spawn tmux")?
-        .wait_with_output()
-        .await
-    {
-        Ok(output) => {
-            if !output.status.success() {
-                log::error!("tmux switch-client failed with status: {}", output.status);
-                Err(eyre!(
-                    "tmux display-message failed with status: {}\n{}",
-                    output.status,
-                    String::from_utf8(output.stderr)?
-                ))
-            } else {
-                String::from_utf8(output.stdout).wrap_err("fail to get the output from stdout")
-            }
+/// Window of a running tmux session.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct TmuxWindow {
+    /// Name given to the window, `None` when tmux names it after the running command.
+    pub name: Option<String>,
+    /// Layout of the panes, as accepted by `select-layout`.
+    pub layout: String,
+    pub panes: Vec<TmuxPane>,
+}
+
+/// Pane of a tmux window.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct TmuxPane {
+    /// Working directory of the process running in the pane.
+    pub path: String,
+    /// Name of the process running in the pane, e.g. `zsh` or `nvim`.
+    pub command: String,
+    /// Command line of the process running in the pane, arguments included and quoted for the
+    /// shell, `None` when it couldn't be read.
+    pub args: Option<String>,
+}
+
+/// Format listing the index, layout and name of a window, the name coming last.
+const WINDOW_FORMAT: &str =
+    "#{window_index}\t#{window_layout}\t#{?automatic-rename,,#{window_name}}";
+
+/// Format listing the window index, process id, command and working directory of a pane, the path
+/// coming last.
+const PANE_FORMAT: &str =
+    "#{window_index}\t#{pane_pid}\t#{pane_current_command}\t#{pane_current_path}";
+
+/// Format printing the id of the pane created by a command.
+const PANE_ID: &str = "#{pane_id}";
+
+/// Multiplexer backed by the `tmux` binary.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct Tmux;
+
+impl Tmux {
+    /// Runs `tmux` with the given arguments and waits for it to finish.
+    ///
+    /// `-u` keeps tmux from replacing the tabs of the formats and the non-ASCII characters of the
+    /// names with `_` when the locale doesn't advertise UTF-8.
+    async fn output(&self, args: &[&str]) -> Result<std::process::Output> {
+        log::debug!("$ tmux {}", args.join(" "));
+        tokio::process::Command::new("tmux")
+            .arg("-u")
+            .args(args)
+            .stdout(std::process::Stdio::piped())
+            .stderr(std::process::Stdio::piped())
+            .spawn()
+            .wrap_err("fail to spawn tmux")?
+            .wait_with_output()
+            .await
+            .wrap_err_with(|| format!("fail to wait for tmux {}", args[0]))
+    }
+
+    /// Runs `tmux` and returns its stdout, failing if it exits with an error status.
+    async fn run(&self, args: &[&str]) -> Result<String> {
+        let output = self.output(args).await?;
+
+        if !output.status.success() {
+            log::error!("tmux {} failed with status: {}", args[0], output.status);
+            return Err(eyre!(
+                "tmux {} failed with status: {}\n{}",
+                args[0],
+                output.status,
+                String::from_utf8(output.stderr)?
+            ));
         }
-        Err(err) => Err(eyre!("fail to get the current tmux session: {}", err)),
+
+        let stdout =
+            String::from_utf8(output.stdout).wrap_err("fail to get the output from stdout")?;
+        log::debug!("tmux {} exited successfully\n{}", args[0], stdout);
+
+        Ok(stdout)
     }
-}
 
-pub async fn is_active() -> Result<bool> {
-    log::debug!("$ tmux info");
-    match tokio::process::Command::new("tmux")
-        .args(["info"])
-        .stdout(std::process::Stdio::piped())
-        .stderr(std::process::Stdio::piped())
-        .spawn()
-        .wrap_err("fail to spawn tmux")?
-        .wait_with_output()
-        .await
-    {
-        Ok(output) => {
-            if !output.status.success() {
-                log::debug!(
-                    "tmux info failed with status: {}\n{}",
-                    output.status,
-                    String::from_utf8(output.stderr)?
-                );
-                Ok(false)
-            } else {
-                log::debug!("tmux info exited successfully\n");
-                Ok(true)
-            }
+    /// Runs `tmux` and reports whether it exited successfully.
+    async fn check(&self, args: &[&str]) -> Result<bool> {
+        let output = self.output(args).await?;
+
+        if !output.status.success() {
+            log::debug!(
+                "tmux {} failed with status: {}\n{}",
+                args[0],
+                output.status,
+                String::from_utf8(output.stderr)?
+            );
         }
-        Err(err) => Err(eyre!("fail to get the current tmux session: {}", err)),
+
+        Ok(output.status.success())
     }
 }
 
-pub async fn attach(session: &str) -> Result<bool> {
-    let session = session.replace('.', "·");
-
-    log::debug!("$ tmux attach -t {}", session);
-    match tokio::process::Command::new("tmux")
-        .args(["attach", "-t", format!("={}", session).as_str()])
-        .stdout(std::process::Stdio::piped())
-        .stderr(std::process::Stdio::piped())
-        .spawn()
-        .wrap_err("fail to spawn tmux")?
-        .wait_with_output()
-        .await
-    {
-        Ok(output) => {
-            if !output.status.success() {
-                log::error!("tmux attach failed with status: {}", output.status);
-                Err(eyre!(
-                    "tmux attach failed with status: {}\n{}",
-                    output.status,
-                    String::from_utf8(output.stderr)?
-                ))
-            } else {
-                log::debug!(
-                    "tmux switch-client exited successfully\n{}",
-                    String::from_utf8(output.stdout)?
-                );
-                Ok(true)
-            }
+impl Multiplexer for Tmux {
+    async fn kill_session(&self, session: &str) -> Result<()> {
+        self.run(&["kill-session", "-t", &name::target(session)])
+            .await
+            .wrap_err_with(|| format!("fail to kill tmux session {}", session))?;
+        Ok(())
+    }
+
+    async fn switch_client(&self, session: &str) -> Result<()> {
+        self.run(&["switch-client", "-t", &name::target(session)])
+            .await
+            .wrap_err_with(|| format!("fail to switch client to session {}", session))?;
+        Ok(())
+    }
+
+    async fn sessions(&self) -> Result<Vec<TmuxSession>> {
+        let stdout = self
+            .run(&["list-sessions", "-F", SESSION_FORMAT])
+            .await
+            .wrap_err("fail to list the tmux sessions")?;
+
+        stdout.split('\n').filter(|s| !s.is_empty()).map(TmuxSession::parse).collect()
+    }
+
+    async fn current_session(&self) -> Result<Option<String>> {
+        // Outside a client, tmux would answer with the most recently used session.
+        if std::env::var_os("TMUX").is_none() {
+            return Ok(None);
         }
-        Err(err) => Err(eyre!("fail attach to session {}: {}", session, err)),
+
+        let stdout = self
+            .run(&["display-message", "-p", "#{session_name}"])
+            .await
+            .wrap_err("fail to get the current tmux session")?;
+        Ok(Some(name::decode(stdout.trim_end_matches('\n'))))
     }
-}
 
-pub async fn new_session(session: &str) -> Result<()> {
-    let name = session.replace('.', "·");
-
-    log::debug!("$ tmux new-session -s {} -c {} -d", name, session);
-    match tokio::process::Command::new("tmux")
-        .args(["new-session", "-s", &name, "-c", session, "-d"])
-        .stdout(std::process::Stdio::piped())
-        .stderr(std::process::Stdio::piped())
-        .spawn()
-        .wrap_err("fail to spawn tmux")?
-        .wait_with_output()
-        .await
-    {
-        Ok(output) => {
-            if !output.status.success() {
-                log::error!("tmux new-session failed with status: {}", output.status);
-                Err(eyre!(
-                    "tmux new-session failed with status: {}\n{}",
-                    output.status,
-                    String::from_utf8(output.stderr)?
-                ))
-            } else {
-                log::debug!(
-                    "tmux switch-client exited successfully\n{}",
-                    String::from_utf8(output.stdout)?
-                );
-                Ok(())
-            }
+    async fn is_active(&self) -> Result<bool> {
+        self.check(&["info"]).await
+    }
+
+    async fn attach(&self, session: &str) -> Result<bool> {
+        self.run(&["attach", "-t", &name::target(session)])
+            .await
+            .wrap_err_with(|| format!("fail attach to session {}", session))?;
+        Ok(true)
+    }
+
+    async fn new_session(
+        &self,
+        session: &str,
+        cwd: &str,
+        options: &SessionOptions,
+    ) -> Result<String> {
+        let name = name::encode(session);
+        let mut args = vec!["new-session", "-s", &name, "-c", cwd, "-d", "-P", "-F", PANE_ID];
+        for env in &options.env {
+            args.extend(["-e", env]);
         }
-        Err(err) => Err(eyre!("fail to create new session {}: {}", session, err)),
+
+        let stdout = self
+            .run(&args)
+            .await
+            .wrap_err_with(|| format!("fail to create new session {}", session))?;
+        let pane = stdout.trim();
+
+        // Targeting the pane sets the option of its session.
+        self.run(&["set-option", "-t", pane, PATH_OPTION, &name::encode(cwd)])
+            .await
+            .wrap_err_with(|| format!("fail to tag session {} with its path", session))?;
+        Ok(pane.to_string())
+    }
+
+    async fn has_session(&self, session: &str) -> Result<bool> {
+        self.check(&["has-session", "-t", &name::target(session)]).await
     }
+
+    async fn new_window(&self, session: &str, name: Option<&str>, cwd: &str) -> Result<String> {
+        let target = format!("{}:", name::target(session));
+
+        let mut args = vec!["new-window", "-d", "-t", &target, "-c", cwd, "-P", "-F", PANE_ID];
+        if let Some(name) = name {
+            args.extend(["-n", name]);
+        }
+
+        let pane = self
+            .run(&args)
+            .await
+            .wrap_err_with(|| format!("fail to create a new window on session {}", session))?;
+        Ok(pane.trim().to_string())
+    }
+
+    async fn rename_window(&self, target: &str, name: &str) -> Result<()> {
+        self.run(&["rename-window", "-t", target, name])
+            .await
+            .wrap_err_with(|| format!("fail to rename window {}", target))?;
+        Ok(())
+    }
+
+    async fn split_window(&self, target: &str, split: Split, cwd: &str) -> Result<String> {
+        let direction = match split {
+            Split::Vertical => "-v",
+            Split::Horizontal => "-h",
+        };
+
+        let pane = self
+            .run(&["split-window", direction, "-t", target, "-c", cwd, "-P", "-F", PANE_ID])
+            .await
+            .wrap_err_with(|| format!("fail to split pane {}", target))?;
+        Ok(pane.trim().to_string())
+    }
+
+    async fn send_keys(&self, target: &str, keys: &str) -> Result<()> {
+        // Literal keys, so words like `Enter` or `C-c` in the command are typed as is.
+        self.run(&["send-keys", "-l", "-t", target, keys])
+            .await
+            .wrap_err_with(|| format!("fail to send keys to pane {}", target))?;
+        self.run(&["send-keys", "-t", target, "Enter"])
+            .await
+            .wrap_err_with(|| format!("fail to send keys to pane {}", target))?;
+        Ok(())
+    }
+
+    async fn select_layout(&self, target: &str, layout: &str) -> Result<()> {
+        self.run(&["select-layout", "-t", target, layout])
+            .await
+            .wrap_err_with(|| format!("fail to select layout {} on {}", layout, target))?;
+        Ok(())
+    }
+
+    async fn windows(&self, session: &str) -> Result<Vec<TmuxWindow>> {
+        let target = name::target(session);
+        let windows = self
+            .run(&["list-windows", "-t", &target, "-F", WINDOW_FORMAT])
+            .await
+            .wrap_err_with(|| format!("fail to list the windows of session {}", session))?;
+        let panes = self
+            .run(&["list-panes", "-s", "-t", &target, "-F", PANE_FORMAT])
+            .await
+            .wrap_err_with(|| format!("fail to list the panes of session {}", session))?;
+
+        let processes = processes().await.unwrap_or_else(|err| {
+            log::warn!("Can't read the command lines of the panes: {:#}", err);
+            Vec::new()
+        });
+
+        let panes: Vec<(&str, TmuxPane)> = panes
+            .lines()
+            .filter_map(|line| {
+                let mut fields = line.splitn(4, '\t');
+                let (index, pid, command, path) =
+                    (fields.next()?, fields.next()?, fields.next()?, fields.next()?);
+                let args =
+                    pid.parse().ok().and_then(|pid| foreground(&processes, pid, command)).map(
+                        |process| cmdline(process.pid).unwrap_or_else(|| process.args.clone()),
+                    );
+                Some((index, TmuxPane { path: path.into(), command: command.into(), args }))
+            })
+            .collect();
+
+        windows
+            .lines()
+            .map(|line| {
+                let mut fields = line.splitn(3, '\t');
+                let (Some(index), Some(layout), Some(name)) =
+                    (fields.next(), fields.next(), fields.next())
+                else {
+                    return Err(eyre!("missing window fields: {}", line));
+                };
+                Ok(TmuxWindow {
+                    name: Some(name.to_string()).filter(|name| !name.is_empty()),
+                    layout: layout.to_string(),
+                    panes: panes
+                        .iter()
+                        .filter(|(window, _)| *window == index)
+                        .map(|(_, pane)| pane.clone())
+                        .collect(),
+                })
+            })
+            .collect()
+    }
+}
+
+/// Process listed by `ps`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Process {
+    pid: u32,
+    ppid: u32,
+    /// Command line as printed by `ps`, its arguments joined by spaces.
+    args: String,
 }
 
-pub async fn has_session(session: &str) -> Result<bool> {
-    let session = session.replace('.', "·");
-
-    log::debug!("$ tmux has-session -t {}", session);
-    match tokio::process::Command::new("tmux")
-        .args(["has-session", "-t", format!("={}", session).as_str()])
-        .stdout(std::process::Stdio::piped())
-        .stderr(std::process::Stdio::piped())
-        .spawn()
-        .wrap_err("fail to spawn tmux")?
-        .wait_with_output()
+/// Lists the running processes with their parent and command line.
+async fn processes() -> Result<Vec<Process>> {
+    let output = tokio::process::Command::new("ps")
+        .args(["-A", "-o", "pid=,ppid=,args="])
+        .output()
         .await
-    {
-        Ok(output) => {
-            if !output.status.success() {
-                log::error!("tmux has-session failed with status: {}", output.status);
-                Ok(false)
-            } else {
-                log::debug!(
-                    "tmux has-session exited successfully\n{}",
-                    String::from_utf8(output.stdout)?
-                );
-                Ok(true)
-            }
-        }
-        Err(err) => Err(eyre!("fail to check if tmux has the session {}: {}", session, err)),
+        .wrap_err("fail to run ps")?;
+
+    if !output.status.success() {
+        return Err(eyre!("ps failed with status: {}", output.status));
+    }
+
+    Ok(String::from_utf8_lossy(&output.stdout).lines().filter_map(Process::parse).collect())
+}
+
+impl Process {
+    /// Parses a line printed by `ps -o pid=,ppid=,args=`.
+    fn parse(line: &str) -> Option<Self> {
+        let (pid, rest) = line.trim_start().split_once(char::is_whitespace)?;
+        let (ppid, args) = rest.trim_start().split_once(char::is_whitespace)?;
+        Some(Self { pid: pid.parse().ok()?, ppid: ppid.parse().ok()?, args: args.trim().into() })
+    }
+
+    /// Name of the program, as tmux shows it in `pane_current_command`.
+    fn name(&self) -> &str {
+        let program = self.args.split(' ').next().unwrap_or_default();
+        program.rsplit('/').next().unwrap_or_default().trim_start_matches('-')
     }
 }
 
-pub async fn set(session: &str) -> Result<()> {
-    if !has_session(session).await? {
-        new_session(session).await?
+/// Finds the process of the `command` a pane runs, given the `pid` of the pane process.
+///
+/// Programs started from the shell of the pane are its children, while programs the pane was
+/// created with are the pane process itself.
+fn foreground<'a>(processes: &'a [Process], pid: u32, command: &str) -> Option<&'a Process> {
+    let command = command.trim_start_matches('-');
+    processes
+        .iter()
+        .find(|process| process.ppid == pid && process.name() == command)
+        .or_else(|| processes.iter().find(|process| process.pid == pid))
+        .filter(|process| process.name() == command)
+}
+
+/// Reads the arguments of the process `pid` from `/proc`, keeping the spaces they contain.
+fn cmdline(pid: u32) -> Option<String> {
+    let cmdline = std::fs::read(format!("/proc/{}/cmdline", pid)).ok()?;
+    let args: Vec<String> = cmdline
+        .split(|byte| *byte == 0)
+        .filter(|arg| !arg.is_empty())
+        .map(|arg| String::from_utf8_lossy(arg).into_owned())
+        .collect();
+
+    if args.is_empty() {
+        return None;
     }
 
-    if is_active().await? {
-        switch_client(session).await?;
+    Some(args.iter().map(|arg| quote(arg)).collect::<Vec<_>>().join(" "))
+}
+
+/// Quotes `arg` for the shell unless it only contains characters the shell reads literally.
+fn quote(arg: &str) -> String {
+    let plain = |c: char| c.is_ascii_alphanumeric() || "-_./=:,+@%".contains(c);
+    if !arg.is_empty() && arg.chars().all(plain) {
+        arg.to_string()
     } else {
-        attach(session).await?;
+        crate::layout::shell_quote(arg)
     }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
 
-    Ok(())
+    #[test]
+    fn pane_commands_keep_their_arguments() {
+        let processes: Vec<Process> = [
+            "  100     1 -zsh",
+            "  200   100 /usr/bin/cargo watch -x run",
+            "  201   200 rustc --edition 2021",
+            "  300     1 nvim src/main.rs",
+        ]
+        .into_iter()
+        .filter_map(Process::parse)
+        .collect();
+
+        assert_eq!(
+            processes[1],
+            Process { pid: 200, ppid: 100, args: "/usr/bin/cargo watch -x run".into() }
+        );
+        let args = |pid, command| foreground(&processes, pid, command).map(|p| p.args.as_str());
+        assert_eq!(args(100, "cargo"), Some("/usr/bin/cargo watch -x run"));
+        assert_eq!(args(300, "nvim"), Some("nvim src/main.rs"));
+        assert_eq!(args(100, "zsh"), Some("-zsh"));
+        assert_eq!(args(100, "python"), None);
+        assert_eq!(quote("it's here"), r"'it'\''s here'");
+        assert_eq!(quote("--port=8000"), "--port=8000");
+    }
+
+    #[test]
+    fn sessions_are_parsed_from_the_format() {
+        let line = "/src/my·app\t1\t3\t1700000100\t1700000000\t/src/my·app\t/src/my.app\twith tab";
+
+        assert_eq!(
+            TmuxSession::parse(line).unwrap(),
+            TmuxSession {
+                name: "/src/my.app".into(),
+                path: "/src/my.app\twith tab".into(),
+                attached: 1,
+                windows: 3,
+                activity: 1_700_000_100,
+                created: 1_700_000_000,
+                source: Some("/src/my.app".into()),
+            }
+        );
+        assert_eq!(TmuxSession::parse("name\t0\t1\t0\t0\t\t/").unwrap().source, None);
+        assert!(TmuxSession::parse("name\t1\t3").is_err());
+        assert!(TmuxSession::parse("name\tyes\t3\t0\t0\t\t/").is_err());
+    }
 }
@@ -0,0 +1,249 @@
+use std::collections::BTreeMap;
+use std::sync::{Mutex, MutexGuard};
+
+use color_eyre::eyre::{eyre, Result};
+
+use super::{Multiplexer, SessionOptions, TmuxPane, TmuxSession, TmuxWindow};
+use crate::layout::Split;
+
+/// In-memory multiplexer that mimics a tmux server.
+#[derive(Debug, Default)]
+pub struct Fake {
+    state: Mutex<State>,
+}
+
+/// Observable state of the [`Fake`] multiplexer.
+#[derive(Debug, Default, Clone)]
+pub struct State {
+    /// Names of the running sessions, in creation order.
+    pub sessions: Vec<String>,
+    /// Session the client is attached to.
+    pub current: Option<String>,
+    /// Environment each session was created with.
+    pub env: BTreeMap<String, Vec<String>>,
+    /// Directory each session was created in.
+    pub paths: BTreeMap<String, String>,
+    /// Directory each session was created for by sessionizer, as in `@sessionizer_path`.
+    pub sources: BTreeMap<String, String>,
+    /// Windows of every session created through the fake.
+    pub windows: BTreeMap<String, Vec<TmuxWindow>>,
+    /// Session, window index and pane index of every pane id.
+    pub pane_ids: BTreeMap<String, (String, usize, usize)>,
+    /// Time of the last activity of every session, in ticks of [`State::clock`].
+    pub activity: BTreeMap<String, u64>,
+    /// Incremented by every call, standing for the time.
+    pub clock: u64,
+    /// Whether the process runs inside a tmux client.
+    pub active: bool,
+    /// Every call made against the multiplexer, formatted as `command args...`.
+    pub calls: Vec<String>,
+    /// Number of panes created so far, used to generate pane ids.
+    pub panes: usize,
+}
+
+/// Command panes run until keys are sent to them.
+pub const SHELL: &str = "zsh";
+
+impl State {
+    fn pane(&mut self) -> String {
+        self.panes += 1;
+        format!("%{}", self.panes - 1)
+    }
+
+    /// Adds a pane running the shell in `cwd` to the `window` of `session` and returns its id.
+    fn add_pane(&mut self, session: &str, window: usize, cwd: &str) -> String {
+        let id = self.pane();
+        let Some(target) = self.windows.get_mut(session).and_then(|w| w.get_mut(window)) else {
+            return id;
+        };
+        target.panes.push(TmuxPane {
+            path: cwd.to_string(),
+            command: SHELL.to_string(),
+            args: Some(SHELL.to_string()),
+        });
+        let index = target.panes.len() - 1;
+        self.pane_ids.insert(id.clone(), (session.to_string(), window, index));
+        id
+    }
+
+    /// Returns the window containing the `pane` and the index of the pane in it.
+    fn locate(&mut self, pane: &str) -> Option<(&mut TmuxWindow, usize)> {
+        let (session, window, index) = self.pane_ids.get(pane)?.clone();
+        Some((self.windows.get_mut(&session)?.get_mut(window)?, index))
+    }
+}
+
+impl Fake {
+    /// Creates a fake server running inside a client with the given sessions.
+    pub fn with_sessions(sessions: &[&str]) -> Self {
+        let fake = Self::default();
+        {
+            let mut state = fake.state();
+            state.active = true;
+            state.sessions = sessions.iter().map(|s| s.to_string()).collect();
+            state.current = state.sessions.last().cloned();
+        }
+        fake
+    }
+
+    /// Locks and returns the fake state.
+    pub fn state(&self) -> MutexGuard<'_, State> {
+        self.state.lock().unwrap()
+    }
+
+    fn record(&self, call: &str, args: &str) -> MutexGuard<'_, State> {
+        let mut state = self.state();
+        state.calls.push(format!("{} {}", call, args).trim_end().to_string());
+        state.clock += 1;
+        state
+    }
+}
+
+impl Multiplexer for Fake {
+    async fn kill_session(&self, session: &str) -> Result<()> {
+        let mut state = self.record("kill-session", session);
+        let len = state.sessions.len();
+        state.sessions.retain(|s| s != session);
+        if state.sessions.len() == len {
+            return Err(eyre!("can't find session: {}", session));
+        }
+        if state.current.as_deref() == Some(session) {
+            state.current = None;
+        }
+        state.windows.remove(session);
+        Ok(())
+    }
+
+    async fn switch_client(&self, session: &str) -> Result<()> {
+        let mut state = self.record("switch-client", session);
+        if !state.sessions.iter().any(|s| s == session) {
+            return Err(eyre!("can't find session: {}", session));
+        }
+        state.current = Some(session.to_string());
+        let clock = state.clock;
+        state.activity.insert(session.to_string(), clock);
+        Ok(())
+    }
+
+    async fn sessions(&self) -> Result<Vec<TmuxSession>> {
+        let state = self.record("list-sessions", "");
+        let sessions = state
+            .sessions
+            .iter()
+            .map(|name| TmuxSession {
+                name: name.clone(),
+                path: state.paths.get(name).cloned().unwrap_or_default(),
+                attached: (state.active && state.current.as_ref() == Some(name)) as usize,
+                windows: state.windows.get(name).map_or(1, Vec::len),
+                activity: state.activity.get(name).copied().unwrap_or_default(),
+                created: 0,
+                source: state.sources.get(name).cloned(),
+            })
+            .collect();
+        Ok(sessions)
+    }
+
+    async fn current_session(&self) -> Result<Option<String>> {
+        let state = self.record("display-message", "");
+        Ok(state.current.clone().filter(|_| state.active))
+    }
+
+    async fn is_active(&self) -> Result<bool> {
+        Ok(self.record("info", "").active)
+    }
+
+    async fn attach(&self, session: &str) -> Result<bool> {
+        let mut state = self.record("attach", session);
+        if !state.sessions.iter().any(|s| s == session) {
+            return Err(eyre!("can't find session: {}", session));
+        }
+        state.current = Some(session.to_string());
+        let clock = state.clock;
+        state.activity.insert(session.to_string(), clock);
+        state.active = true;
+        Ok(true)
+    }
+
+    async fn new_session(
+        &self,
+        session: &str,
+        cwd: &str,
+        options: &SessionOptions,
+    ) -> Result<String> {
+        let call =
+            if session == cwd { session.to_string() } else { format!("{} {}", session, cwd) };
+        let mut state = self.record("new-session", &call);
+        if state.sessions.iter().any(|s| s == session) {
+            return Err(eyre!("duplicate session: {}", session));
+        }
+        state.env.insert(session.to_string(), options.env.clone());
+        state.paths.insert(session.to_string(), cwd.to_string());
+        state.sources.insert(session.to_string(), cwd.to_string());
+        state.sessions.push(session.to_string());
+        state.windows.insert(session.to_string(), vec![TmuxWindow::default()]);
+        Ok(state.add_pane(session, 0, cwd))
+    }
+
+    async fn has_session(&self, session: &str) -> Result<bool> {
+        Ok(self.record("has-session", session).sessions.iter().any(|s| s == session))
+    }
+
+    async fn new_window(&self, session: &str, name: Option<&str>, cwd: &str) -> Result<String> {
+        let call = format!("{} {} {}", session, name.unwrap_or_default(), cwd);
+        let mut state = self.record("new-window", &call);
+        let Some(windows) = state.windows.get_mut(session) else {
+            return Ok(state.pane());
+        };
+        windows.push(TmuxWindow { name: name.map(String::from), ..Default::default() });
+        let window = windows.len() - 1;
+        Ok(state.add_pane(session, window, cwd))
+    }
+
+    async fn rename_window(&self, target: &str, name: &str) -> Result<()> {
+        let mut state = self.record("rename-window", &format!("{} {}", target, name));
+        if let Some((window, _)) = state.locate(target) {
+            window.name = Some(name.to_string());
+        }
+        Ok(())
+    }
+
+    async fn split_window(&self, target: &str, split: Split, cwd: &str) -> Result<String> {
+        let split = match split {
+            Split::Vertical => "vertical",
+            Split::Horizontal => "horizontal",
+        };
+        let mut state = self.record("split-window", &format!("{} {} {}", target, split, cwd));
+        match state.pane_ids.get(target).cloned() {
+            Some((session, window, _)) => Ok(state.add_pane(&session, window, cwd)),
+            None => Ok(state.pane()),
+        }
+    }
+
+    async fn send_keys(&self, target: &str, keys: &str) -> Result<()> {
+        let mut state = self.record("send-keys", &format!("{} {}", target, keys));
+        if let Some((window, index)) = state.locate(target) {
+            let pane = &mut window.panes[index];
+            match keys.strip_prefix("cd ") {
+                Some(path) => pane.path = path.trim_matches('\'').to_string(),
+                None => {
+                    pane.command = keys.split(' ').next().unwrap_or_default().to_string();
+                    pane.args = Some(keys.to_string());
+                }
+            }
+        }
+        Ok(())
+    }
+
+    async fn select_layout(&self, target: &str, layout: &str) -> Result<()> {
+        let mut state = self.record("select-layout", &format!("{} {}", target, layout));
+        if let Some((window, _)) = state.locate(target) {
+            window.layout = layout.to_string();
+        }
+        Ok(())
+    }
+
+    async fn windows(&self, session: &str) -> Result<Vec<TmuxWindow>> {
+        let state = self.record("list-windows", session);
+        state.windows.get(session).cloned().ok_or_else(|| eyre!("can't find session: {}", session))
+    }
+}
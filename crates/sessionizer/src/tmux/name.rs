@@ -0,0 +1,110 @@
+//! Encoding of session names into names tmux accepts.
+//!
+//! tmux reads `.` and `:` as separators in targets and replaces them when creating a session,
+//! expands `#` as a format and escapes `\` and control characters. Names are encoded before
+//! reaching tmux and decoded when read back, so any session name, path-like or not, survives the
+//! round trip.
+
+/// Characters tmux doesn't keep in session names and their readable replacements.
+const REPLACEMENTS: [(char, char); 2] = [('.', '·'), (':', '꞉')];
+
+/// Checks if `c` has to be percent-encoded: `%` itself, `#` which tmux expands as a format,
+/// `\` and control characters which tmux escapes, and the replacement characters.
+fn is_escaped(c: char) -> bool {
+    matches!(c, '%' | '#' | '\\')
+        || c.is_control()
+        || REPLACEMENTS.iter().any(|(_, replacement)| *replacement == c)
+}
+
+/// Returns the name of the tmux session called `name` by sessionizer.
+pub fn encode(name: &str) -> String {
+    let mut encoded = String::with_capacity(name.len());
+    for c in name.chars() {
+        if is_escaped(c) {
+            for byte in c.to_string().bytes() {
+                encoded.push_str(&format!("%{:02X}", byte));
+            }
+        } else if let Some((_, replacement)) = REPLACEMENTS.iter().find(|(from, _)| *from == c) {
+            encoded.push(*replacement);
+        } else {
+            encoded.push(c);
+        }
+    }
+    encoded
+}
+
+/// Returns the sessionizer name of the tmux session called `name`.
+///
+/// A `%` not followed by two hexadecimal digits, which can only come from sessions created
+/// outside sessionizer, is kept as it is.
+pub fn decode(name: &str) -> String {
+    let mut bytes = Vec::with_capacity(name.len());
+    let mut chars = name.char_indices();
+    while let Some((i, c)) = chars.next() {
+        let hex = name.get(i + 1..i + 3).filter(|hex| hex.bytes().all(|b| b.is_ascii_hexdigit()));
+        match hex {
+            Some(hex) if c == '%' => {
+                bytes.push(u8::from_str_radix(hex, 16).unwrap());
+                chars.nth(1);
+            }
+            _ => {
+                let c = REPLACEMENTS.iter().find(|(_, to)| *to == c).map_or(c, |(from, _)| *from);
+                bytes.extend_from_slice(c.to_string().as_bytes());
+            }
+        }
+    }
+    String::from_utf8_lossy(&bytes).into_owned()
+}
+
+/// Returns the `-t` target matching the session called `name` exactly.
+///
+/// Without the `=` prefix tmux would also match sessions by prefix or pattern, and read a leading
+/// `$` as a session id.
+pub fn target(name: &str) -> String {
+    format!("={}", encode(name))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn names_survive_the_round_trip() {
+        let names = [
+            "/home/me/src/api",
+            "/home/me/.dotfiles",
+            "/srv/a:b/c.d",
+            "my app",
+            "=exact",
+            "~home",
+            "$1",
+            "-flag",
+            "back\\slash\\",
+            "already·dotted and꞉colon",
+            "#{session_id} 100% %41",
+            "new\nline\tand\u{7}bell",
+            "ünïcødé/日本",
+            "",
+        ];
+
+        for name in names {
+            let encoded = encode(name);
+            assert!(!encoded.contains(['.', ':', '#', '\\']), "{:?}", encoded);
+            assert!(!encoded.chars().any(char::is_control), "{:?}", encoded);
+            assert_eq!(decode(&encoded), name, "{:?}", encoded);
+        }
+    }
+
+    #[test]
+    fn dots_and_colons_stay_readable() {
+        assert_eq!(encode("/src/my.app:v2"), "/src/my·app꞉v2");
+        assert_eq!(target("$1"), "=$1");
+    }
+
+    #[test]
+    fn foreign_names_decode_leniently() {
+        assert_eq!(decode("50%"), "50%");
+        assert_eq!(decode("%zz%4"), "%zz%4");
+        assert_eq!(decode("a·b"), "a.b");
+    }
+}
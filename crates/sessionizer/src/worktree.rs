@@ -0,0 +1,370 @@
+//! Git worktrees, read straight from the repository metadata.
+//!
+//! Every linked worktree has a folder in `<repo>/.git/worktrees/<id>`, whose `gitdir` file points
+//! back to the worktree's `.git` file and whose `HEAD` names the checked out branch.
+
+use std::path::{Component, Path, PathBuf};
+
+use clap::{Parser, Subcommand};
+use color_eyre::eyre::{bail, eyre, Result, WrapErr};
+use serde::{Deserialize, Serialize};
+
+use crate::config::Config;
+use crate::state::State;
+use crate::tmux::Multiplexer;
+
+/// Path of new worktrees when no `worktree.path` is configured.
+const DEFAULT_PATH: &str = "{parent}/{repo}-{branch}";
+
+/// Settings of the worktrees created by sessionizer.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct Options {
+    /// Path of new worktrees, relative to the repository unless absolute.
+    ///
+    /// `{parent}` is replaced by the folder containing the repository, `{repo}` by its name and
+    /// `{branch}` by the branch, with `/` replaced by `-`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub path: Option<String>,
+    /// Offer to remove the linked worktree of a killed session.
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub remove: bool,
+}
+
+impl Options {
+    pub fn is_empty(&self) -> bool {
+        *self == Self::default()
+    }
+
+    /// Returns the path of a new worktree of `repo` for `branch`.
+    fn path(&self, repo: &Path, branch: &str) -> PathBuf {
+        let parent = repo.parent().unwrap_or(repo).to_string_lossy();
+        let name = repo.file_name().unwrap_or_default().to_string_lossy();
+
+        let path = self
+            .path
+            .as_deref()
+            .unwrap_or(DEFAULT_PATH)
+            .replace("{parent}", &parent)
+            .replace("{repo}", &name)
+            .replace("{branch}", &branch.replace('/', "-"));
+
+        normalize(&repo.join(shellexpand::tilde(&path).as_ref()))
+    }
+}
+
+/// Resolves the `.` and `..` components of `path` without touching the filesystem, since a new
+/// worktree doesn't exist yet.
+fn normalize(path: &Path) -> PathBuf {
+    let mut normalized = PathBuf::new();
+    for component in path.components() {
+        match component {
+            Component::CurDir => {}
+            Component::ParentDir => {
+                normalized.pop();
+            }
+            component => normalized.push(component),
+        }
+    }
+    normalized
+}
+
+#[derive(Debug, Subcommand)]
+pub enum Commands {
+    /// Create a worktree of a repository and open it as a session.
+    #[clap(name = "new")]
+    New {
+        /// Repository to create the worktree from.
+        repo: String,
+        /// Branch to check out, created from the current one if it doesn't exist.
+        branch: String,
+    },
+}
+
+#[derive(Debug, Parser)]
+#[command(name = "worktree")]
+#[command(about = "Manage git worktrees")]
+pub struct Cli {
+    #[command(subcommand)]
+    pub command: Commands,
+}
+
+pub async fn run(mux: &impl Multiplexer, config: Config, state: State, cli: Cli) -> Result<()> {
+    match cli.command {
+        Commands::New { repo, branch } => {
+            let path = create(&config.worktree, &repo, &branch).await?;
+            crate::sessions::new(mux, &config, state, Some(path)).await
+        }
+    }
+}
+
+/// Runs `git` inside `dir` and returns its stdout, failing if it exits with an error status.
+async fn git(dir: &Path, args: &[&str]) -> Result<String> {
+    log::debug!("$ git -C {} {}", dir.display(), args.join(" "));
+    let output = tokio::process::Command::new("git")
+        .arg("-C")
+        .arg(dir)
+        .args(args)
+        .stdin(std::process::Stdio::null())
+        .output()
+        .await
+        .wrap_err("fail to spawn git")?;
+
+    if !output.status.success() {
+        return Err(eyre!(
+            "git {} failed with status: {}\n{}",
+            args[0],
+            output.status,
+            String::from_utf8_lossy(&output.stderr).trim_end()
+        ));
+    }
+
+    String::from_utf8(output.stdout).wrap_err("fail to get the output from stdout")
+}
+
+/// Creates a worktree of `repo` for `branch` and returns its path.
+pub async fn create(options: &Options, repo: &str, branch: &str) -> Result<String> {
+    let repo = std::fs::canonicalize(repo).wrap_err_with(|| format!("fail to find {}", repo))?;
+    if !repo.join(".git").exists() {
+        bail!("{} is not a git repository", repo.display());
+    }
+    let main = main_worktree(&repo).unwrap_or_else(|| repo.clone());
+
+    let path = options.path(&main, branch).to_string_lossy().to_string();
+    let reference = format!("refs/heads/{}", branch);
+    let exists = git(&repo, &["rev-parse", "--verify", "--quiet", &reference]).await.is_ok();
+
+    let mut args = vec!["worktree", "add", &path];
+    match exists {
+        true => args.push(branch),
+        false => args.extend(["-b", branch]),
+    }
+    git(&repo, &args).await.wrap_err_with(|| format!("fail to create the worktree {}", path))?;
+
+    Ok(path)
+}
+
+/// Asks for a branch and creates a worktree of `repo` for it.
+pub async fn ask_and_create(options: &Options, repo: &str) -> Result<String> {
+    let branch = crate::prompt::ask(&format!("Branch of the new worktree of {}:", repo))?;
+    if branch.is_empty() {
+        bail!("no branch given for the new worktree");
+    }
+
+    create(options, repo, &branch).await
+}
+
+/// Checks if the worktree at `path` has uncommitted or untracked changes.
+async fn is_dirty(path: &Path) -> Result<bool> {
+    Ok(!git(path, &["status", "--porcelain"]).await?.trim().is_empty())
+}
+
+/// Offers to remove the linked worktree at `path`, keeping it if it has uncommitted changes.
+pub async fn offer_removal(path: &str) -> Result<()> {
+    let worktree = Path::new(path);
+    let repo = main_worktree(worktree).ok_or_else(|| eyre!("{} is not a worktree", path))?;
+
+    if is_dirty(worktree).await? {
+        println!("Keeping the worktree {}, it has uncommitted changes.", path);
+        return Ok(());
+    }
+
+    if crate::prompt::confirm(&format!("Remove the worktree {}?", path))? {
+        git(&repo, &["worktree", "remove", path])
+            .await
+            .wrap_err_with(|| format!("fail to remove the worktree {}", path))?;
+    }
+
+    Ok(())
+}
+
+/// Administrative folders of the linked worktrees of `repo`.
+fn admin_dirs(repo: &Path) -> Vec<PathBuf> {
+    let Ok(entries) = std::fs::read_dir(repo.join(".git").join("worktrees")) else {
+        return vec![];
+    };
+
+    let mut dirs: Vec<PathBuf> =
+        entries.filter_map(|e| e.ok()).map(|e| e.path()).filter(|p| p.is_dir()).collect();
+    dirs.sort();
+    dirs
+}
+
+/// Lists the paths of the linked worktrees of the `repo` main worktree.
+///
+/// Worktrees whose folder no longer exists are skipped.
+pub fn list(repo: &Path) -> Vec<PathBuf> {
+    admin_dirs(repo)
+        .iter()
+        .filter_map(|admin| std::fs::read_to_string(admin.join("gitdir")).ok())
+        .filter_map(|gitdir| Path::new(gitdir.trim()).parent().map(Path::to_path_buf))
+        .filter(|worktree| worktree.is_dir())
+        .collect()
+}
+
+/// Folders whose modification reveals a worktree being added or removed from `repo`.
+pub fn watched(repo: &Path) -> [PathBuf; 2] {
+    let git = repo.join(".git");
+    [git.join("worktrees"), git]
+}
+
+/// Administrative folder of the linked worktree at `path`, read from its `.git` file.
+fn admin_dir(path: &Path) -> Option<PathBuf> {
+    let git = path.join(".git");
+    if !git.is_file() {
+        return None;
+    }
+
+    let text = std::fs::read_to_string(git).ok()?;
+    let admin = Path::new(text.trim().strip_prefix("gitdir:")?.trim());
+    Some(path.join(admin))
+}
+
+/// Branch checked out according to the `HEAD` file in `admin`, or the short commit id when
+/// detached.
+fn branch(admin: &Path) -> Option<String> {
+    let head = std::fs::read_to_string(admin.join("HEAD")).ok()?;
+    let head = head.trim();
+
+    match head.strip_prefix("ref:") {
+        Some(reference) => {
+            let reference = reference.trim();
+            Some(reference.strip_prefix("refs/heads/").unwrap_or(reference).to_string())
+        }
+        None => Some(head.chars().take(7).collect()),
+    }
+}
+
+/// Returns the branch checked out in the git repository or linked worktree containing `path`.
+pub fn current_branch(path: &str) -> Option<String> {
+    let root = Path::new(path).ancestors().find(|dir| dir.join(".git").exists())?;
+    let git = root.join(".git");
+    match git.is_dir() {
+        true => branch(&git),
+        false => branch(&admin_dir(root)?),
+    }
+}
+
+/// Returns the main worktree of the repository `path` is a linked worktree of.
+fn main_worktree(path: &Path) -> Option<PathBuf> {
+    let admin = admin_dir(path)?;
+
+    let common = std::fs::read_to_string(admin.join("commondir")).ok()?;
+    let common = std::fs::canonicalize(admin.join(common.trim())).ok()?;
+    common.parent().map(Path::to_path_buf)
+}
+
+/// Returns the `repo@branch` name of the linked worktree at `path`, if it is one.
+pub fn name(path: &str) -> Option<String> {
+    let admin = admin_dir(Path::new(path))?;
+    let main = main_worktree(Path::new(path))?;
+    let repo = main.file_name()?.to_string_lossy().to_string();
+
+    Some(format!("{}@{}", repo, branch(&admin)?))
+}
+
+#[cfg(test)]
+pub mod tests {
+    use super::*;
+
+    /// Lays out the metadata `git worktree add <worktree> -b <branch>` leaves in `repo`.
+    pub fn add(repo: &Path, worktree: &Path, branch: &str) {
+        let id = worktree.file_name().unwrap();
+        let admin = repo.join(".git/worktrees").join(id);
+        std::fs::create_dir_all(&admin).unwrap();
+        std::fs::create_dir_all(worktree).unwrap();
+
+        let gitdir = worktree.join(".git");
+        std::fs::write(admin.join("gitdir"), format!("{}\n", gitdir.display())).unwrap();
+        std::fs::write(admin.join("commondir"), "../..\n").unwrap();
+        std::fs::write(admin.join("HEAD"), format!("ref: refs/heads/{}\n", branch)).unwrap();
+        std::fs::write(gitdir, format!("gitdir: {}\n", admin.display())).unwrap();
+    }
+
+    #[test]
+    fn linked_worktrees_are_listed_and_named_after_their_branch() {
+        let dir = tempfile::tempdir().unwrap();
+        let repo = dir.path().join("api");
+        std::fs::create_dir_all(repo.join(".git")).unwrap();
+        add(&repo, &dir.path().join("api-feature"), "feature/login");
+        add(&repo, &dir.path().join("gone"), "old");
+        std::fs::remove_dir_all(dir.path().join("gone")).unwrap();
+
+        let worktrees = list(&repo);
+        assert_eq!(worktrees, vec![dir.path().join("api-feature")]);
+
+        assert_eq!(name(&worktrees[0].to_string_lossy()).as_deref(), Some("api@feature/login"));
+        assert_eq!(name(&repo.to_string_lossy()), None);
+
+        let branch = |path: &Path| current_branch(&path.to_string_lossy());
+        assert_eq!(branch(&worktrees[0]).as_deref(), Some("feature/login"));
+        assert_eq!(branch(&repo), None);
+        std::fs::write(repo.join(".git/HEAD"), "ref: refs/heads/main\n").unwrap();
+        assert_eq!(branch(&repo).as_deref(), Some("main"));
+        assert_eq!(branch(dir.path()), None);
+
+        std::fs::create_dir_all(repo.join("src/bin")).unwrap();
+        std::fs::create_dir_all(worktrees[0].join("docs")).unwrap();
+        assert_eq!(branch(&repo.join("src/bin")).as_deref(), Some("main"));
+        assert_eq!(branch(&worktrees[0].join("docs")).as_deref(), Some("feature/login"));
+    }
+
+    #[test]
+    fn detached_worktrees_use_the_short_commit() {
+        let dir = tempfile::tempdir().unwrap();
+        let repo = dir.path().join("api");
+        let worktree = dir.path().join("review");
+        add(&repo, &worktree, "main");
+        let admin = repo.join(".git/worktrees/review");
+        std::fs::write(admin.join("HEAD"), "0123456789abcdef\n").unwrap();
+
+        assert_eq!(name(&worktree.to_string_lossy()).as_deref(), Some("api@0123456"));
+    }
+
+    fn git_init(dir: &Path) {
+        for args in [&["init", "-q"][..], &["commit", "-q", "--allow-empty", "-m", "init"]] {
+            let status = std::process::Command::new("git")
+                .args(["-c", "user.name=test", "-c", "user.email=test@example.com"])
+                .arg("-C")
+                .arg(dir)
+                .args(args)
+                .status()
+                .unwrap();
+            assert!(status.success());
+        }
+    }
+
+    #[test]
+    fn new_worktrees_go_next_to_the_repository() {
+        let options = Options::default();
+        assert_eq!(
+            options.path(Path::new("/src/api"), "feature/login"),
+            Path::new("/src/api-feature-login")
+        );
+
+        let options = Options { path: Some("../trees/{branch}".into()), ..Default::default() };
+        assert_eq!(options.path(Path::new("/src/api"), "fix"), Path::new("/src/trees/fix"));
+    }
+
+    #[tokio::test]
+    async fn worktrees_are_created_and_only_removed_when_clean() {
+        let dir = tempfile::tempdir().unwrap();
+        let repo = dir.path().join("api");
+        std::fs::create_dir(&repo).unwrap();
+        git_init(&repo);
+
+        let path =
+            create(&Options::default(), &repo.to_string_lossy(), "feature/login").await.unwrap();
+        let worktree = std::fs::canonicalize(dir.path()).unwrap().join("api-feature-login");
+        assert_eq!(Path::new(&path), worktree);
+        assert_eq!(name(&path).as_deref(), Some("api@feature/login"));
+        assert!(!is_dirty(&worktree).await.unwrap());
+
+        std::fs::write(worktree.join("notes"), "wip").unwrap();
+        assert!(is_dirty(&worktree).await.unwrap());
+        offer_removal(&path).await.unwrap();
+        assert!(worktree.exists());
+
+        let err = create(&Options::default(), &dir.path().to_string_lossy(), "x").await;
+        assert!(err.unwrap_err().to_string().contains("is not a git repository"));
+    }
+}
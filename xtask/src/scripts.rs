@@ -7,9 +7,8 @@ use std::error::Error;
 pub fn run(args: &cli::RunArgs) -> Result<(), Box<dyn Error>> {
     let mut arguments = vec!["run", "--bin", &args.name];
 
-    match &args.args {
-        Some(args) => arguments.extend(args.iter().map(|s| s.as_str())),
-        None => {}
+    if let Some(args) = &args.args {
+        arguments.extend(args.iter().map(|s| s.as_str()));
     }
 
     cmd("cargo", arguments).read()?;
@@ -30,10 +29,7 @@ pub fn build(args: &cli::BuildArgs) -> Result<(), Box<dyn Error>> {
 }
 
 fn release(name: &str) -> Result<(), Box<dyn Error>> {
-    let buid_args = cli::BuildArgs {
-        name: name.to_string(),
-        release: true,
-    };
+    let buid_args = cli::BuildArgs { name: name.to_string(), release: true };
 
     build(&buid_args)?;
 
@@ -76,19 +72,8 @@ pub fn github(args: &cli::GithubArgs) -> Result<(), Box<dyn Error>> {
     cmd!("git", "push", "origin", &version).run()?;
     println!("{$magenta}Creating {[yellow]} release{/$}", &version);
     cmd!("gh", "release", "create", &version, "--title", &version, "--notes", &notes).run()?;
-    println!(
-        "{$magenta}Uploading {[yellow]} release binary{/$}",
-        &version
-    );
-    cmd!(
-        "gh",
-        "release",
-        "upload",
-        &version,
-        &target_path,
-        "--clobber"
-    )
-    .run()?;
+    println!("{$magenta}Uploading {[yellow]} release binary{/$}", &version);
+    cmd!("gh", "release", "upload", &version, &target_path, "--clobber").run()?;
 
     Ok(())
 }